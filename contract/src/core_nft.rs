@@ -6,11 +6,13 @@ use fraction::Fraction;
 use gate::{GateId, ValidGateId};
 use near_env::PanicMessage;
 use near_sdk::{
+    base64,
     borsh::{self, BorshDeserialize, BorshSerialize},
     env,
     json_types::{U128, U64},
+    log,
     serde::{Deserialize, Serialize},
-    AccountId, CryptoHash,
+    serde_json, AccountId, Balance, CryptoHash,
 };
 use std::collections::HashMap;
 
@@ -229,15 +231,191 @@ pub struct Collectible {
     pub gate_id: GateId,
     /// The account id that created this `Collectible`.
     pub id_creador: AccountId,
-    /// Indicates how many `Token`s can be minted out of this `Collectible`.
-    pub cantidad_actual: u16,
-    /// The list of `TokenId`s actually minted out of this `Collectible`.
-    pub tokens_creados: Vec<TokenId>,
+    /// Indicates how many `Token`s can still be minted out of this `Collectible`,
+    /// or `None` if it is an open edition with no cap on supply.
+    pub cantidad_actual: Option<u32>,
+    /// Indicates how many `Token`s have been minted out of this `Collectible`.
+    pub cantidad_creada: u32,
+    /// UNIX epoch (in miliseconds) until `Token`s can be minted, if any.
+    pub crear_hasta: Option<Timestamp>,
     /// Indicates the comision as percentage (in NEARs) to be paid to `id_creador`
     /// every time a minted token out of this `Collectible` is reselled.
     pub comision: Fraction,
+    /// Whether this `Collectible` was cancelled.
+    /// Tokens of a cancelled `Collectible` cannot be minted and can be burned by its creator.
+    pub cancelado: bool,
+    /// Descending price schedule of the primary sale, if any.
+    pub venta_holandesa: Option<VentaHolandesa>,
     /// Additional info provided by NEP-177.
     pub metadata: Metadata,
+    /// Hashes of the previous versions of `metadata`, the most recent first.
+    pub historial_metadata: Vec<RegistroMetadata>,
+    /// Details of the event this `Collectible` gives access to, if any.
+    /// They are also serialized into `metadata.extra`.
+    pub detalles: Option<DetallesEvento>,
+}
+
+impl Collectible {
+    /// Indicates whether no more `Token`s can be minted at `ahora`,
+    /// either because the supply ran out or because `crear_hasta` has passed.
+    pub fn agotado(&self, ahora: Timestamp) -> bool {
+        self.cantidad_actual == Some(0)
+            || self.crear_hasta.map_or(false, |crear_hasta| ahora >= crear_hasta)
+    }
+}
+
+/// Details of the event a `Collectible` gives access to.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm"), derive(PartialEq, Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct DetallesEvento {
+    /// Name of the venue, ex. "Luna Park".
+    pub lugar: String,
+    /// Street address of the venue.
+    pub direccion: Option<String>,
+    /// Geographic coordinates of the venue.
+    pub coordenadas: Option<Coordenadas>,
+    /// UNIX epoch datetime (in miliseconds) when doors open.
+    pub apertura_puertas_en: Option<Timestamp>,
+    /// Minimum age required to attend, if any.
+    pub edad_minima: Option<u8>,
+    /// Kind of event.
+    pub categoria: CategoriaEvento,
+}
+
+/// Geographic coordinates, expressed in decimal degrees.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm"), derive(PartialEq, Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Coordenadas {
+    /// Between `-90` and `90`.
+    pub latitud: f64,
+    /// Between `-180` and `180`.
+    pub longitud: f64,
+}
+
+/// Kinds of events a `Collectible` can give access to.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm"), derive(Debug))]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum CategoriaEvento {
+    Concierto,
+    Deporte,
+    Teatro,
+    Conferencia,
+    Festival,
+    Otro,
+}
+
+/// A previous version of the `metadata` of a `Collectible`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm"), derive(PartialEq, Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct RegistroMetadata {
+    /// Base64-encoded sha256 hash of the JSON of the replaced `metadata`.
+    pub hash: String,
+    /// When the `metadata` was replaced, expressed in miliseconds.
+    pub actualizado_en: Timestamp,
+}
+
+impl Metadata {
+    /// Returns the base64-encoded sha256 hash of the JSON of this `Metadata`.
+    pub fn hash(&self) -> String {
+        base64::encode(env::sha256(&serde_json::to_vec(self).unwrap()))
+    }
+
+    /// Returns this `Metadata` of a `Collectible` merged with the fields of one of its tokens.
+    /// The edition is appended to `titulo`, and the rest of the fields are added to `extra`.
+    pub fn con_token(mut self, metadata_token: &MetadataToken) -> Self {
+        let edicion = match self.copias {
+            None => format!("#{}", metadata_token.edicion),
+            Some(copias) => format!("#{} of {}", metadata_token.edicion, copias),
+        };
+        self.titulo = Some(match self.titulo {
+            None => edicion,
+            Some(titulo) => format!("{} {}", titulo, edicion),
+        });
+
+        let mut extra = match self.extra.take() {
+            None => serde_json::Map::new(),
+            Some(extra) => match serde_json::from_str(&extra) {
+                Ok(serde_json::Value::Object(extra)) => extra,
+                _ => {
+                    let mut map = serde_json::Map::new();
+                    map.insert("extra".to_string(), serde_json::Value::String(extra));
+                    map
+                }
+            },
+        };
+        if let Ok(serde_json::Value::Object(campos)) = serde_json::to_value(metadata_token) {
+            extra.extend(campos.into_iter().filter(|(_, valor)| !valor.is_null()));
+        }
+        self.extra = Some(serde_json::Value::Object(extra).to_string());
+        self
+    }
+}
+
+/// Descending price schedule (Dutch auction) for the primary sale of a `Collectible`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm"), derive(PartialEq, Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct PrecioHolandes {
+    /// Price (in NEARs) at `comienzo_en`.
+    pub precio_inicial: U128,
+    /// Floor price (in NEARs), the price never decays below it.
+    pub precio_minimo: U128,
+    /// UNIX epoch (in miliseconds) when the sale starts.
+    pub comienzo_en: Timestamp,
+    /// Miliseconds between each price decrease.
+    pub intervalo: Timestamp,
+    /// Amount (in NEARs) the price decreases every `intervalo`.
+    pub decremento: U128,
+    /// If `true`, proceeds are held so early buyers can claim
+    /// the difference down to the final clearing price.
+    pub reembolso: bool,
+}
+
+impl PrecioHolandes {
+    /// Returns the price at `ahora`.
+    pub fn precio_en(&self, ahora: Timestamp) -> Balance {
+        if ahora <= self.comienzo_en {
+            return self.precio_inicial.0;
+        }
+        let intervalos = ((ahora - self.comienzo_en) / self.intervalo) as u128;
+        self.precio_inicial
+            .0
+            .saturating_sub(self.decremento.0.saturating_mul(intervalos))
+            .max(self.precio_minimo.0)
+    }
+}
+
+/// State of the Dutch auction primary sale of a `Collectible`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm"), derive(PartialEq, Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct VentaHolandesa {
+    /// The price schedule.
+    pub precio: PrecioHolandes,
+    /// How many `Token`s were sold through this sale.
+    pub vendidos: u64,
+    /// Price paid for the last `Token` sold.
+    pub ultimo_precio: U128,
+    /// Amount already withdrawn by the creator when `reembolso` is set.
+    pub retirado: U128,
+}
+
+impl VentaHolandesa {
+    /// Returns the final clearing price, if already known:
+    /// the last price paid when `agotado`, or the floor price once reached.
+    pub fn precio_final(&self, agotado: bool, ahora: Timestamp) -> Option<Balance> {
+        if agotado {
+            Some(self.ultimo_precio.0)
+        } else if self.precio.precio_en(ahora) == self.precio.precio_minimo.0 {
+            Some(self.precio.precio_minimo.0)
+        } else {
+            None
+        }
+    }
 }
 
 /// Represents a copy made out of a given ticket.
@@ -264,6 +442,10 @@ pub struct Token {
     pub approvals: HashMap<AccountId, TokenApproval>,
     /// Counter to assign next approval ID.
     pub approval_counter: U64,
+    /// Price (in NEARs) this `Token` was last sold for through `nft_transfer_payout`.
+    pub precio_venta: Option<U128>,
+    /// Fields of this `Token` that are not shared with the other tokens of its `gate_id`.
+    pub metadata_token: MetadataToken,
 
     #[borsh_skip]
     /// Additional info defined by NEP-177.
@@ -271,6 +453,22 @@ pub struct Token {
     pub metadata: Metadata,
 }
 
+/// Metadata of a single `Token`, merged over the `Metadata` of its `Collectible`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Default)]
+#[cfg_attr(not(target_arch = "wasm"), derive(PartialEq, Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct MetadataToken {
+    /// Serial number of this `Token` within its `Collectible`, starting at `1`.
+    pub edicion: u32,
+    /// Seat assigned by the creator of the `Collectible`, if any.
+    pub asiento: Option<String>,
+    /// Tier assigned by the creator of the `Collectible`, if any.
+    pub nivel: Option<String>,
+    /// Display name chosen by the current owner, if any.
+    /// It is cleared every time the `Token` is transferred.
+    pub nombre_titular: Option<String>,
+}
+
 /// Associated metadata with a `GateId` as defined by NEP-177
 ///
 /// Doc-comments for these fields were taken from:
@@ -289,7 +487,7 @@ pub struct Metadata {
     /// Required if `media` is included.
     pub media_hash: Option<String>,
     /// Number of copias of this set of metadata in existence when token was minted.
-    pub copias: Option<u16>,
+    pub copias: Option<u32>,
     /// UNIX epoch datetime (in miliseconds) when token was issued or minted.
     pub emitido_en: Option<Timestamp>,
     /// UNIX epoch datetime (in miliseconds) when token expires.
@@ -308,6 +506,41 @@ pub struct Metadata {
     pub referencia_hash: Option<String>,
 }
 
+/// Events emitted by the NFT contract, logged following the NEP-297 format.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde", tag = "event", content = "data", rename_all = "snake_case")]
+pub enum Evento {
+    /// A `Token` was transferred for a `precio` through `nft_transfer_payout`.
+    Venta {
+        token_id: TokenId,
+        gate_id: GateId,
+        vendedor_id: AccountId,
+        comprador_id: AccountId,
+        precio: U128,
+    },
+    /// A `Token` was burned, either by its owner or by the creator of its `Collectible`.
+    Quema { token_id: TokenId, gate_id: GateId, owner_id: AccountId },
+    /// The supply of a `Collectible` was changed by its creator.
+    CambioCantidad { gate_id: GateId, copias_anterior: u32, copias: u32 },
+}
+
+impl Evento {
+    /// Logs this `Evento` as `EVENT_JSON:{...}`.
+    pub fn emit(&self) {
+        #[derive(Serialize)]
+        #[serde(crate = "near_sdk::serde")]
+        struct EventoLog<'a> {
+            standard: &'static str,
+            version: &'static str,
+            #[serde(flatten)]
+            evento: &'a Evento,
+        }
+
+        let evento = EventoLog { standard: "my_tickets", version: "1.0.0", evento: self };
+        log!("EVENT_JSON:{}", serde_json::to_string(&evento).unwrap());
+    }
+}
+
 /// Represents an individual approval by some marketplace account id.
 #[derive(BorshDeserialize, BorshSerialize, Serialize)]
 #[cfg_attr(not(target_arch = "wasm"), derive(PartialEq, Debug, Deserialize))]
@@ -412,7 +645,7 @@ pub mod nep178 {
     use near_env::near_ext;
     use near_sdk::{
         ext_contract,
-        json_types::{ValidAccountId, U64},
+        json_types::{ValidAccountId, U128, U64},
         Promise,
     };
 
@@ -430,7 +663,7 @@ pub mod nep178 {
     }
 
     /// This interface defines methods to be called
-    /// when approval or removal happened in a NFT contrato.
+    /// when approval, price update or removal happened in a NFT contrato.
     #[near_ext]
     #[ext_contract(market)]
     pub trait NonFungibleTokenApprovalsReceiver {
@@ -448,7 +681,17 @@ pub mod nep178 {
             owner_id: ValidAccountId,
         );
 
+        fn nft_on_price_update(
+            &mut self,
+            token_id: TokenId,
+            owner_id: ValidAccountId,
+            aprobados_id: U64,
+            min_precio: U128,
+        );
+
         fn nft_on_revoke(&mut self, token_id: TokenId);
+
+        fn batch_on_revoke(&mut self, token_ids: Vec<TokenId>);
     }
 }
 
@@ -488,6 +731,10 @@ pub mod nep181 {
 pub struct NftApproveMsg {
     /// Indicates the minimum price (in NEARs) requested by owner to pay for the token.
     pub min_precio: U128,
+    /// When present, the Marketplace accepts this offer instead of listing the token.
+    pub oferta_id: Option<U64>,
+    /// When present, UNIX epoch (in miliseconds) when the listing expires.
+    pub expira_en: Option<Timestamp>,
 }
 
 /// Represents the payload that arrives to the Marketplace contract,
@@ -504,4 +751,11 @@ pub struct MarketApproveMsg {
     pub gate_id: Option<ValidGateId>,
     /// Represents the `id_creador` of the ticket of the token being approved if present.
     pub id_creador: Option<AccountId>,
+    /// Represents the offer the owner accepts for this token, if any.
+    pub oferta_id: Option<U64>,
+    /// Represents when the listing expires, if any.
+    pub expira_en: Option<Timestamp>,
+    /// Represents the approval id of the token.
+    /// It is only set in `batch_on_approve`, since `nft_on_approve` receives it as argument.
+    pub aprobados_id: Option<U64>,
 }
@@ -11,14 +11,19 @@ pub use crate::context::*;
 mod core_nft;
 mod market;
 mod context;
+mod migraciones;
+#[cfg(test)]
+mod tests;
 
 use fraction::Fraction;
+use migraciones::{assert_admin, migrar, releer, ContratoNftBase, Migracion};
 use nep171::NonFungibleTokenCore;
 use nep177::{NFTContractMetadata, NonFungibleTokenMetadata};
 use nep178::NonFungibleTokenApprovalMgmt;
 use nep181::NonFungibleTokenEnumeration;
 use near_env::{near_ext, near_log, PanicMessage};
 use near_sdk::{
+    base64,
     borsh::{self, BorshDeserialize, BorshSerialize},
     collections::{LookupMap, UnorderedMap, UnorderedSet},
     env, ext_contract,
@@ -40,6 +45,15 @@ pub struct ContratoNft {
     tickets_de_creador: LookupMap<AccountId, UnorderedSet<GateId>>,
     tokens: UnorderedMap<TokenId, Token>,
     tokens_de_address: LookupMap<AccountId, UnorderedSet<TokenId>>,
+    /// Tokens creados de cada ticket.
+    tokens_por_ticket: LookupMap<GateId, UnorderedSet<TokenId>>,
+    /// ID del proximo token a crear, los IDs de tokens quemados no se reutilizan.
+    siguiente_token_id: u64,
+    /// Cantidad de tokens y total pagado por cada comprador en una venta holandesa,
+    /// indexado por `<gate_id>:<account_id>`.
+    pagos_holandes: LookupMap<String, (u64, Balance)>,
+    /// Avance de la migracion del estado, `None` si no hay una migracion en curso.
+    migracion: Option<Migracion>,
 
     id_admin: AccountId,
     metadata: NFTContractMetadata,
@@ -55,6 +69,10 @@ enum Keys {
     Tokens,
     TokensPorDueño,
     TokensPorDueñoValor { hash_id_dueño: CryptoHash },
+    PagosHolandes,
+    TokensPorTicket,
+    TokensPorTicketValor { hash_gate_id: CryptoHash },
+    EdicionesMigracion,
 }
 
 /// Metodos del contrato principal
@@ -84,6 +102,10 @@ impl ContratoNft {
             tickets_de_creador: LookupMap::new(Keys::TicketsPorCreador),
             tokens: UnorderedMap::new(Keys::Tokens),
             tokens_de_address: LookupMap::new(Keys::TokensPorDueño),
+            tokens_por_ticket: LookupMap::new(Keys::TokensPorTicket),
+            siguiente_token_id: 0,
+            pagos_holandes: LookupMap::new(Keys::PagosHolandes),
+            migracion: None,
             id_admin: id_admin.as_ref().to_string(),
             metadata,
             fee_reventa,
@@ -92,8 +114,11 @@ impl ContratoNft {
     }
 
     /// Crea una nueva seri de tickets indentificando por IDs
-    /// `cantidad` indica la cantidad maxima
+    /// `cantidad` indica la cantidad maxima, sin `cantidad` el ticket es una edicion abierta
     /// `comision` indica la comision, en porcentaje, que se paga al creador al momento de la venta
+    /// `precio_holandes` indica, si se da, el precio descendente de la venta primaria
+    /// `crear_hasta` indica, si se da, hasta cuando se pueden comprar tokens del ticket
+    /// `detalles` indica, si se dan, los detalles del evento, que tambien se guardan en `extra`
     ///
     /// Entre comision y fee no pueden superar 1, de lo contrario da error
     pub fn crear_ticket(
@@ -102,12 +127,15 @@ impl ContratoNft {
         gate_id: ValidGateId,
         titulo: String,
         descripcion: String,
-        cantidad: u16,
+        cantidad: Option<u32>,
         comision: Fraction,
         media: Option<String>,
         media_hash: Option<String>,
         referencia: Option<String>,
         referencia_hash: Option<String>,
+        precio_holandes: Option<PrecioHolandes>,
+        crear_hasta: Option<Timestamp>,
+        detalles: Option<DetallesEvento>,
     ) {
         let gate_id = gate_id.to_string();
 
@@ -118,68 +146,78 @@ impl ContratoNft {
         if self.tickets.get(&gate_id).is_some() {
             Panic::GateIdAlreadyExists { gate_id }.panic();
         }
-        if cantidad == 0 {
+        if cantidad == Some(0) {
             Panic::ZeroSupplyNotAllowed { gate_id }.panic();
         }
-        if titulo.len() > 140 {
-            Panic::InvalidArgument { gate_id, reason: "Titulo no puede tener mas de 140 caracteres".to_string() }
+
+        let ahora = env::block_timestamp() / 1_000_000;
+        let metadata = Metadata {
+            titulo: Some(titulo),
+            descripcion: Some(descripcion),
+            media,
+            media_hash,
+            copias: cantidad,
+            emitido_en: Some(ahora),
+            expira_en: None,
+            comienzo_en: Some(ahora),
+            actualizado_en: None,
+            extra: detalles.as_ref().map(|detalles| serde_json::to_string(detalles).unwrap()),
+            referencia,
+            referencia_hash,
+        };
+        validar_metadata(&gate_id, &metadata);
+        if let Some(detalles) = &detalles {
+            validar_detalles(&gate_id, detalles, ahora);
+        }
+
+        if let Some(precio) = &precio_holandes {
+            if precio.precio_minimo.0 > precio.precio_inicial.0 {
+                Panic::InvalidArgument {
+                    gate_id,
+                    reason: "`precio_minimo` no puede superar a `precio_inicial`".to_string(),
+                }
+                .panic();
+            }
+            if precio.intervalo == 0 {
+                Panic::InvalidArgument {
+                    gate_id,
+                    reason: "`intervalo` debe ser positivo".to_string(),
+                }
                 .panic();
+            }
         }
-        if descripcion.len() > 1024 {
+
+        if crear_hasta.map_or(false, |crear_hasta| crear_hasta <= ahora) {
             Panic::InvalidArgument {
                 gate_id,
-                reason: "`La descripcion no puede sobrepasar los 1024 caracteres".to_string(),
+                reason: "`crear_hasta` debe ser posterior al momento actual".to_string(),
             }
             .panic();
         }
 
-        macro_rules! check {
-            ($arg:ident) => {{
-                if let Some(val) = &$arg {
-                    if val.len() > 1024 {
-                        Panic::InvalidArgument {
-                            gate_id,
-                            reason: concat!("`", stringify!($arg), "` exceeds 1024 chars")
-                                .to_string(),
-                        }
-                        .panic();
-                    }
-                }
-            }};
-        }
-
-        check!(media);
-        check!(media_hash);
-        check!(referencia);
-        check!(referencia_hash);
-
         if env::predecessor_account_id() != self.id_admin {
             Panic::AdminRestrictedOperation.panic();
         }
 
         let id_creador = AccountId::from(id_creador);
-        let ahora = env::block_timestamp() / 1_000_000;
 
         let ticket = Collectible {
             gate_id,
             id_creador,
             cantidad_actual: cantidad,
-            tokens_creados: Vec::new(),
+            cantidad_creada: 0,
+            crear_hasta,
             comision,
-            metadata: Metadata {
-                titulo: Some(titulo),
-                descripcion: Some(descripcion),
-                media,
-                media_hash,
-                copias: Some(cantidad),
-                emitido_en: Some(ahora),
-                expira_en: None,
-                comienzo_en: Some(ahora),
-                actualizado_en: None,
-                extra: None,
-                referencia,
-                referencia_hash,
-            },
+            cancelado: false,
+            venta_holandesa: precio_holandes.map(|precio| VentaHolandesa {
+                precio,
+                vendidos: 0,
+                ultimo_precio: U128(0),
+                retirado: U128(0),
+            }),
+            metadata,
+            historial_metadata: Vec::new(),
+            detalles,
         };
         self.tickets.insert(&ticket.gate_id, &ticket);
 
@@ -194,6 +232,78 @@ impl ContratoNft {
         self.tickets_de_creador.insert(&ticket.id_creador, &guia);
     }
 
+    /// Actualiza la metadata de un ticket, los campos que no se dan no cambian
+    /// Si cambia `media` o `referencia` tambien debe darse su nuevo hash
+    /// El hash de la metadata anterior se guarda en `historial_metadata`
+    /// Puede ejecutarse solo por el creador del ticket
+    pub fn actualizar_ticket(
+        &mut self,
+        gate_id: ValidGateId,
+        titulo: Option<String>,
+        descripcion: Option<String>,
+        media: Option<String>,
+        media_hash: Option<String>,
+        referencia: Option<String>,
+        referencia_hash: Option<String>,
+    ) {
+        let gate_id: GateId = From::from(gate_id);
+        let mut ticket = self.get_ticket_de_creador(&gate_id);
+        let ahora = env::block_timestamp() / 1_000_000;
+
+        registrar_metadata(&mut ticket, ahora);
+
+        let metadata = &mut ticket.metadata;
+        validar_cambio_url(&gate_id, "media", &metadata.media, &media, &media_hash);
+        validar_cambio_url(
+            &gate_id,
+            "referencia",
+            &metadata.referencia,
+            &referencia,
+            &referencia_hash,
+        );
+        // Solo se validan los campos que cambian, el resto ya se valido al guardarlos.
+        let cambios = Metadata {
+            titulo,
+            descripcion,
+            media: media.filter(|media| Some(media) != metadata.media.as_ref()),
+            media_hash,
+            referencia: referencia.filter(|url| Some(url) != metadata.referencia.as_ref()),
+            referencia_hash,
+            ..Metadata::default()
+        };
+        validar_metadata(&gate_id, &cambios);
+
+        metadata.titulo = cambios.titulo.or(metadata.titulo.take());
+        metadata.descripcion = cambios.descripcion.or(metadata.descripcion.take());
+        metadata.media = cambios.media.or(metadata.media.take());
+        metadata.media_hash = cambios.media_hash.or(metadata.media_hash.take());
+        metadata.referencia = cambios.referencia.or(metadata.referencia.take());
+        metadata.referencia_hash = cambios.referencia_hash.or(metadata.referencia_hash.take());
+        metadata.actualizado_en = Some(ahora);
+
+        self.tickets.insert(&gate_id, &ticket);
+    }
+
+    /// Retorna los tickets de la categoria indicada,
+    /// paginados sobre los tickets de esa categoria
+    pub fn get_tickets_por_categoria(
+        &self,
+        categoria: CategoriaEvento,
+        from_index: Option<U64>,
+        limit: Option<u32>,
+    ) -> Vec<Collectible> {
+        let from_index = from_index.map_or(0, |s| s.0 as usize);
+        let limit = limit.unwrap_or(u32::MAX) as usize;
+        self.tickets
+            .values()
+            .filter(|ticket| {
+                ticket.detalles.as_ref().map_or(false, |detalles| detalles.categoria == categoria)
+            })
+            .skip(from_index)
+            .take(limit)
+            .collect()
+    }
+
     /// Retona un ticket indicado segun ID
     pub fn get_ticket_por_id(&self, gate_id: ValidGateId) -> Option<Collectible> {
         let gate_id = gate_id.to_string();
@@ -223,20 +333,40 @@ impl ContratoNft {
         }
     }
 
+    /// Retorna los tokens creados de un ticket, paginados desde `from_index` hasta `limit` tokens
+    pub fn get_tokens_de_ticket(
+        &self,
+        gate_id: ValidGateId,
+        from_index: Option<U64>,
+        limit: Option<u32>,
+    ) -> Vec<Token> {
+        match self.tokens_por_ticket.get(gate_id.as_ref()) {
+            None => Vec::new(),
+            Some(list) => list
+                .iter()
+                .skip(from_index.map_or(0, |i| i.0 as usize))
+                .take(limit.unwrap_or(u32::MAX) as usize)
+                .map(|token_id| self.get_token(token_id).expect("Token not found"))
+                .collect(),
+        }
+    }
+
     /// Elimina ticket segun ID indicado
     /// Puede ejecutarse solo por `id_creador` y `id_admin` 
     pub fn borrar_ticket(&mut self, gate_id: ValidGateId) {
+        let tiene_tokens = !self.get_tokens_de_ticket(gate_id.clone(), None, Some(1)).is_empty();
         let gate_id: GateId = From::from(gate_id);
         match self.tickets.get(&gate_id) {
             None => Panic::GateIdNotFound { gate_id }.panic(),
             Some(ticket) => {
                 assert!(ticket.gate_id == gate_id);
-                if !ticket.tokens_creados.is_empty() {
+                if tiene_tokens {
                     Panic::GateIdHasTokens { gate_id }.panic();
                 }
                 let pred_id = env::predecessor_account_id();
                 if pred_id == ticket.id_creador || pred_id == self.id_admin {
                     self.tickets.remove(&gate_id).unwrap();
+                    self.tokens_por_ticket.remove(&gate_id);
 
                     let mut cs = self.tickets_de_creador.get(&ticket.id_creador).unwrap();
                     let removed = cs.remove(&gate_id);
@@ -249,21 +379,240 @@ impl ContratoNft {
         }
     }
 
+    /// Cancela un ticket, no se pueden comprar mas tokens del ticket
+    /// y sus tokens se pueden quemar con `quemar_por_lote`
+    /// Puede ejecutarse solo por el creador del ticket o el admin
+    pub fn cancelar_ticket(&mut self, gate_id: ValidGateId) {
+        let gate_id: GateId = From::from(gate_id);
+        match self.tickets.get(&gate_id) {
+            None => Panic::GateIdNotFound { gate_id }.panic(),
+            Some(mut ticket) => {
+                let pred_id = env::predecessor_account_id();
+                if pred_id != ticket.id_creador && pred_id != self.id_admin {
+                    Panic::NotAuthorized { gate_id }.panic();
+                }
+                ticket.cancelado = true;
+                self.tickets.insert(&gate_id, &ticket);
+            }
+        }
+    }
+
+    /// Agrega `cantidad` tokens sin vender a un ticket
+    /// Puede ejecutarse solo por el creador del ticket
+    pub fn agregar_cantidad(&mut self, gate_id: ValidGateId, cantidad: u32) {
+        let gate_id: GateId = From::from(gate_id);
+        let ticket = self.get_ticket_de_creador(&gate_id);
+        let cantidad_actual = get_cantidad_actual(&ticket, cantidad);
+        let copias = ticket.cantidad_creada.checked_add(cantidad_actual);
+        if copias.and_then(|copias| copias.checked_add(cantidad)).is_none() {
+            Panic::InvalidArgument {
+                gate_id,
+                reason: "La cantidad total supera el maximo permitido".to_string(),
+            }
+            .panic();
+        }
+        self.actualizar_cantidad(ticket, cantidad_actual + cantidad);
+    }
+
+    /// Quita `cantidad` tokens sin vender de un ticket,
+    /// la cantidad total nunca queda por debajo de los tokens ya creados
+    /// Puede ejecutarse solo por el creador del ticket
+    pub fn quitar_cantidad(&mut self, gate_id: ValidGateId, cantidad: u32) {
+        let gate_id: GateId = From::from(gate_id);
+        let ticket = self.get_ticket_de_creador(&gate_id);
+        let cantidad_actual = get_cantidad_actual(&ticket, cantidad);
+        if cantidad > cantidad_actual {
+            Panic::SupplyBelowMinted { gate_id, cantidad_creada: ticket.cantidad_creada }.panic();
+        }
+        if ticket.cantidad_creada + cantidad_actual - cantidad == 0 {
+            Panic::ZeroSupplyNotAllowed { gate_id }.panic();
+        }
+        self.actualizar_cantidad(ticket, cantidad_actual - cantidad);
+    }
+
+    /// Asigna el asiento y el nivel de un token, los campos que no se dan no cambian
+    /// Puede ejecutarse solo por el creador del ticket del token
+    pub fn asignar_asiento(
+        &mut self,
+        token_id: TokenId,
+        asiento: Option<String>,
+        nivel: Option<String>,
+    ) {
+        let mut token = match self.tokens.get(&token_id) {
+            None => Panic::TokenIdNotFound { token_id }.panic(),
+            Some(token) => token,
+        };
+        self.get_ticket_de_creador(&token.gate_id);
+        validar_metadata_token(&token.gate_id, "asiento", &asiento);
+        validar_metadata_token(&token.gate_id, "nivel", &nivel);
+
+        let metadata_token = &mut token.metadata_token;
+        metadata_token.asiento = asiento.or(metadata_token.asiento.take());
+        metadata_token.nivel = nivel.or(metadata_token.nivel.take());
+        token.modified_at = env::block_timestamp() / 1_000_000;
+        self.tokens.insert(&token_id, &token);
+    }
+
+    /// Asigna el nombre a mostrar del dueño de un token, o lo borra si no se da
+    /// Se borra cada vez que el token se transfiere
+    /// Puede ejecutarse solo por el dueño del token
+    pub fn asignar_nombre_titular(&mut self, token_id: TokenId, nombre_titular: Option<String>) {
+        let owner_id = env::predecessor_account_id();
+        let mut token = match self.tokens.get(&token_id) {
+            None => Panic::TokenIdNotFound { token_id }.panic(),
+            Some(token) => token,
+        };
+        if owner_id != token.owner_id {
+            Panic::TokenIdNotOwnedBy { token_id, owner_id }.panic();
+        }
+        validar_metadata_token(&token.gate_id, "nombre_titular", &nombre_titular);
+
+        token.metadata_token.nombre_titular = nombre_titular;
+        token.modified_at = env::block_timestamp() / 1_000_000;
+        self.tokens.insert(&token_id, &token);
+    }
+
+    /// Quema un token
+    /// Puede ejecutarse solo por el dueño del token
+    /// Los markets aprobados para el token reciben `batch_on_revoke`
+    pub fn nft_burn(&mut self, token_id: TokenId) -> Promise {
+        let owner_id = env::predecessor_account_id();
+        let token = self.get_token_int(token_id);
+        if owner_id != token.owner_id {
+            Panic::TokenIdNotOwnedBy { token_id, owner_id }.panic();
+        }
+        let mut revocados = Vec::new();
+        for market_id in self.quemar_token(token) {
+            agrupar_por_market(&mut revocados, &market_id, token_id);
+        }
+        self.notificar_revocados(revocados, Vec::new())
+    }
+
+    /// Asigna la fecha de vencimiento de un ticket, en milisegundos,
+    /// una vez vencido sus tokens se pueden quemar con `quemar_por_lote`
+    /// Puede ejecutarse solo por el creador del ticket o el admin
+    pub fn set_expiracion(&mut self, gate_id: ValidGateId, expira_en: Timestamp) {
+        let gate_id: GateId = From::from(gate_id);
+        match self.tickets.get(&gate_id) {
+            None => Panic::GateIdNotFound { gate_id }.panic(),
+            Some(mut ticket) => {
+                let pred_id = env::predecessor_account_id();
+                if pred_id != ticket.id_creador && pred_id != self.id_admin {
+                    Panic::NotAuthorized { gate_id }.panic();
+                }
+                let ahora = env::block_timestamp() / 1_000_000;
+                if expira_en <= ahora {
+                    Panic::InvalidArgument {
+                        gate_id,
+                        reason: "`expira_en` must be in the future".to_string(),
+                    }
+                    .panic();
+                }
+                registrar_metadata(&mut ticket, ahora);
+                ticket.metadata.expira_en = Some(expira_en);
+                ticket.metadata.actualizado_en = Some(ahora);
+                self.tickets.insert(&gate_id, &ticket);
+            }
+        }
+    }
+
+    /// Quema por lote tokens de un ticket cancelado o vencido
+    /// Puede ejecutarse solo por el creador del ticket o el admin
+    pub fn quemar_por_lote(&mut self, gate_id: ValidGateId, token_ids: Vec<TokenId>) -> Promise {
+        if token_ids.len() > 50 {
+            Panic::ExceedTokensToBatchBurn.panic();
+        }
+
+        let gate_id: GateId = From::from(gate_id);
+        let ticket = match self.tickets.get(&gate_id) {
+            None => Panic::GateIdNotFound { gate_id }.panic(),
+            Some(ticket) => ticket,
+        };
+        let pred_id = env::predecessor_account_id();
+        if pred_id != ticket.id_creador && pred_id != self.id_admin {
+            Panic::NotAuthorized { gate_id }.panic();
+        }
+        let ahora = env::block_timestamp() / 1_000_000;
+        let vencido = ticket.metadata.expira_en.map_or(false, |expira_en| ahora >= expira_en);
+        if !ticket.cancelado && !vencido {
+            Panic::GateIdNotBurnable { gate_id }.panic();
+        }
+
+        let mut revocados = Vec::new();
+        let mut errs = Vec::new();
+        for token_id in token_ids {
+            match self.tokens.get(&token_id) {
+                None => errs.push((token_id, Panic::TokenIdNotFound { token_id })),
+                Some(token) if token.gate_id != gate_id => {
+                    let err = Panic::TokenNotOfGate { token_id, gate_id: gate_id.clone() };
+                    errs.push((token_id, err));
+                }
+                Some(token) => {
+                    for market_id in self.quemar_token(token) {
+                        agrupar_por_market(&mut revocados, &market_id, token_id);
+                    }
+                }
+            }
+        }
+        self.notificar_revocados(revocados, errs)
+    }
+
     /// Permite la comprar de un token y retorna token ID
+    ///
+    /// Si el ticket tiene `venta_holandesa` se cobra el precio actual,
+    /// el excedente del deposito se reembolsa.
+    #[payable]
     pub fn comprar_token(&mut self, gate_id: ValidGateId) -> TokenId {
         let gate_id = gate_id.to_string();
 
         match self.tickets.get(&gate_id) {
             None => Panic::GateIdNotFound { gate_id }.panic(),
             Some(mut ticket) => {
-                if ticket.cantidad_actual == 0 {
+                if ticket.cancelado {
+                    Panic::GateIdCancelled { gate_id }.panic()
+                }
+                if ticket.cantidad_actual == Some(0) {
                     Panic::GateIdExhausted { gate_id }.panic()
                 }
+                let ahora = env::block_timestamp() / 1_000_000;
+                if ticket.agotado(ahora) {
+                    Panic::MintingEnded { gate_id }.panic()
+                }
 
                 let owner_id = env::predecessor_account_id();
-                let ahora = env::block_timestamp() / 1_000_000;
+                let deposito = env::attached_deposit();
 
-                let token_id = self.tokens.len();
+                let precio = match &mut ticket.venta_holandesa {
+                    None => 0,
+                    Some(venta) => {
+                        if ahora < venta.precio.comienzo_en {
+                            Panic::SaleNotStarted { gate_id }.panic();
+                        }
+                        let precio = venta.precio.precio_en(ahora);
+                        if deposito < precio {
+                            Panic::NotEnoughDeposit { gate_id, precio: U128(precio) }.panic();
+                        }
+                        venta.vendidos += 1;
+                        venta.ultimo_precio = U128(precio);
+                        if venta.precio.reembolso {
+                            let clave = format!("{}:{}", gate_id, owner_id);
+                            let (cantidad, total) =
+                                self.pagos_holandes.get(&clave).unwrap_or((0, 0));
+                            self.pagos_holandes.insert(&clave, &(cantidad + 1, total + precio));
+                        } else {
+                            Promise::new(ticket.id_creador.clone()).transfer(precio);
+                        }
+                        precio
+                    }
+                };
+                if deposito > precio {
+                    Promise::new(owner_id.clone()).transfer(deposito - precio);
+                }
+
+                let token_id = self.siguiente_token_id;
+                self.siguiente_token_id += 1;
+                let metadata_token =
+                    MetadataToken { edicion: ticket.cantidad_creada + 1, ..Default::default() };
                 let token = Token {
                     token_id: U64::from(token_id),
                     gate_id: gate_id.clone(),
@@ -272,24 +621,143 @@ impl ContratoNft {
                     modified_at: ahora,
                     approvals: HashMap::new(),
                     approval_counter: U64::from(0),
+                    precio_venta: None,
+                    metadata_token,
                     metadata: Metadata::default(),
                 };
                 self.insertar_token(&token);
 
-                ticket.cantidad_actual = ticket.cantidad_actual - 1;
-                ticket.tokens_creados.push(U64(token_id));
+                if let Some(cantidad_actual) = ticket.cantidad_actual.as_mut() {
+                    *cantidad_actual -= 1;
+                }
+                ticket.cantidad_creada += 1;
                 self.tickets.insert(&gate_id, &ticket);
 
+                let mut tids = self.tokens_por_ticket.get(&gate_id).unwrap_or_else(|| {
+                    UnorderedSet::new(Keys::TokensPorTicketValor {
+                        hash_gate_id: crypto_hash(&gate_id),
+                    })
+                });
+                tids.insert(&U64(token_id));
+                self.tokens_por_ticket.insert(&gate_id, &tids);
+
                 U64::from(token_id)
             }
         }
     }
 
+    /// Retorna el precio actual de la venta holandesa de un ticket,
+    /// o `None` si el ticket no tiene venta holandesa.
+    pub fn get_precio_actual(&self, gate_id: ValidGateId) -> Option<U128> {
+        let gate_id = gate_id.to_string();
+        match self.tickets.get(&gate_id) {
+            None => Panic::GateIdNotFound { gate_id }.panic(),
+            Some(ticket) => ticket
+                .venta_holandesa
+                .map(|venta| U128(venta.precio.precio_en(env::block_timestamp() / 1_000_000))),
+        }
+    }
+
+    /// Reembolsa al comprador la diferencia entre lo pagado y el precio final
+    /// de la venta holandesa de un ticket con `reembolso`.
+    /// Retorna el monto reembolsado.
+    pub fn reclamar_reembolso(&mut self, gate_id: ValidGateId) -> U128 {
+        let gate_id = gate_id.to_string();
+        let precio_final = self.get_precio_final(&gate_id);
+
+        let account_id = env::predecessor_account_id();
+        let clave = format!("{}:{}", gate_id, account_id);
+        match self.pagos_holandes.get(&clave) {
+            None => Panic::NoRebateAvailable { gate_id }.panic(),
+            Some((cantidad, total)) => {
+                let reembolso = total - precio_final * cantidad as u128;
+                if reembolso == 0 {
+                    Panic::NoRebateAvailable { gate_id }.panic();
+                }
+                self.pagos_holandes.insert(&clave, &(cantidad, total - reembolso));
+                Promise::new(account_id).transfer(reembolso);
+                U128(reembolso)
+            }
+        }
+    }
+
+    /// Transfiere al creador lo recaudado al precio final
+    /// por la venta holandesa de un ticket con `reembolso`.
+    /// Puede ejecutarse solo por `id_creador`.
+    pub fn retirar_ventas(&mut self, gate_id: ValidGateId) -> U128 {
+        let gate_id = gate_id.to_string();
+        let precio_final = self.get_precio_final(&gate_id);
+
+        let mut ticket = self.tickets.get(&gate_id).unwrap();
+        if env::predecessor_account_id() != ticket.id_creador {
+            Panic::CreatorRestrictedOperation { gate_id }.panic();
+        }
+        let venta = ticket.venta_holandesa.as_mut().unwrap();
+        let monto = precio_final * venta.vendidos as u128 - venta.retirado.0;
+        venta.retirado.0 += monto;
+        self.tickets.insert(&gate_id, &ticket);
+        if monto > 0 {
+            Promise::new(ticket.id_creador).transfer(monto);
+        }
+        U128(monto)
+    }
 
     /* 
      *   Funciones internas 
      */
 
+    /// Retorna el precio final de la venta holandesa con `reembolso` de `gate_id`,
+    /// o da un Panic error si todavia no se conoce.
+    fn get_precio_final(&self, gate_id: &GateId) -> Balance {
+        let gate_id = gate_id.clone();
+        match self.tickets.get(&gate_id) {
+            None => Panic::GateIdNotFound { gate_id }.panic(),
+            Some(ticket) => {
+                let ahora = env::block_timestamp() / 1_000_000;
+                let agotado = ticket.agotado(ahora);
+                match ticket.venta_holandesa {
+                    Some(venta) if venta.precio.reembolso => {
+                        match venta.precio_final(agotado, ahora) {
+                            None => Panic::ClearingPriceNotReached { gate_id }.panic(),
+                            Some(precio_final) => precio_final,
+                        }
+                    }
+                    _ => Panic::NoRebateAvailable { gate_id }.panic(),
+                }
+            }
+        }
+    }
+
+    /// Retorna el ticket `gate_id`,
+    /// o da un Panic error si no existe o si no lo ejecuta su creador.
+    fn get_ticket_de_creador(&self, gate_id: &GateId) -> Collectible {
+        let gate_id = gate_id.clone();
+        match self.tickets.get(&gate_id) {
+            None => Panic::GateIdNotFound { gate_id }.panic(),
+            Some(ticket) => {
+                if env::predecessor_account_id() != ticket.id_creador {
+                    Panic::CreatorRestrictedOperation { gate_id }.panic();
+                }
+                ticket
+            }
+        }
+    }
+
+    /// Guarda `ticket` con `cantidad_actual` tokens sin vender,
+    /// manteniendo `copias` en la metadata, y emite `Evento::CambioCantidad`.
+    fn actualizar_cantidad(&mut self, mut ticket: Collectible, cantidad_actual: u32) {
+        let copias = ticket.cantidad_creada + cantidad_actual;
+        let evento = Evento::CambioCantidad {
+            gate_id: ticket.gate_id.clone(),
+            copias_anterior: ticket.metadata.copias.unwrap_or_default(),
+            copias,
+        };
+        ticket.cantidad_actual = Some(cantidad_actual);
+        ticket.metadata.copias = Some(copias);
+        self.tickets.insert(&ticket.gate_id, &ticket);
+        evento.emit();
+    }
+
     /// Retorna todos los tokens de un dueño indicado por ID
     pub fn get_tokens_de_dueno(&self, owner_id: ValidAccountId) -> Vec<Token> {
         match self.tokens_de_address.get(owner_id.as_ref()) {
@@ -313,7 +781,7 @@ impl ContratoNft {
             Some(mut token) => {
                 assert!(token.token_id == token_id);
                 let ticket = self.tickets.get(&token.gate_id).expect("ID no encontrado");
-                token.metadata = ticket.metadata;
+                token.metadata = ticket.metadata.con_token(&token.metadata_token);
                 Some(token)
             }
         }
@@ -341,6 +809,23 @@ impl ContratoNft {
         self.tokens_de_address.insert(&token.owner_id, &tids);
     }
 
+    /// Quita `token` del contrato y de los tokens creados de su ticket.
+    /// Retorna los markets que tenian aprobado el token.
+    fn quemar_token(&mut self, token: Token) -> Vec<AccountId> {
+        self.borrar_token_int(token.token_id, &token.owner_id);
+        if let Some(mut tids) = self.tokens_por_ticket.get(&token.gate_id) {
+            tids.remove(&token.token_id);
+            self.tokens_por_ticket.insert(&token.gate_id, &tids);
+        }
+        Evento::Quema {
+            token_id: token.token_id,
+            gate_id: token.gate_id,
+            owner_id: token.owner_id,
+        }
+        .emit();
+        token.approvals.into_iter().map(|(market_id, _)| market_id).collect()
+    }
+
     /// Metodo interno llamado por borrar token
     fn borrar_token_int(&mut self, token_id: TokenId, owner_id: &AccountId) {
         match self.tokens_de_address.get(&owner_id) {
@@ -357,40 +842,284 @@ impl ContratoNft {
         }
     }
 
-    /// Aprobar un token por lote
-    pub fn aprobar_por_lote(
+    /// Transfiere `token_id` a `receiver_id` en nombre de `sender_id`.
+    /// Si `sender_id` no es el dueño, debe estar aprobado y, según NEP-178,
+    /// `enforce_approval_id` se compara contra su `aprobados_id`.
+    /// Para el dueño `enforce_approval_id` se ignora.
+    ///
+    /// `balance` es el precio de venta, si lo hay.
+    /// Una cuenta aprobada no puede transferir por debajo del `min_precio` de su aprobación.
+    fn transferir_token(
         &mut self,
-        tokens: Vec<(TokenId, U128)>,
-        account_id: ValidAccountId,
-    ) -> Promise {
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        token_id: TokenId,
+        enforce_approval_id: Option<U64>,
+        memo: Option<String>,
+        balance: Option<U128>,
+    ) -> Result<(), Panic> {
+        let mut token = match self.tokens.get(&token_id) {
+            None => return Err(Panic::TokenIdNotFound { token_id }),
+            Some(token) => token,
+        };
+
+        if sender_id != &token.owner_id {
+            match token.approvals.get(sender_id) {
+                None => {
+                    return Err(Panic::SenderNotAuthToTransfer { sender_id: sender_id.clone() })
+                }
+                Some(TokenApproval { aprobados_id, min_precio }) => {
+                    if let Some(enforce_approval_id) = enforce_approval_id {
+                        if aprobados_id != &enforce_approval_id {
+                            return Err(Panic::EnforceApprovalFailed {
+                                aprobados_id: *aprobados_id,
+                                sender_id: sender_id.clone(),
+                                enforce_approval_id,
+                            });
+                        }
+                    }
+                    let precio = balance.unwrap_or(U128(0));
+                    if precio.0 < min_precio.0 {
+                        return Err(Panic::PriceBelowMinimum {
+                            balance: precio,
+                            min_precio: *min_precio,
+                            token_id,
+                        });
+                    }
+                }
+            }
+        }
+
+        if &token.owner_id == receiver_id {
+            return Err(Panic::ReceiverIsOwner);
+        }
+        if let Some(memo) = memo {
+            log!("Memo: {}", memo);
+        }
+
+        self.borrar_token_int(token_id, &token.owner_id);
+
+        if let Some(precio) = balance {
+            Evento::Venta {
+                token_id,
+                gate_id: token.gate_id.clone(),
+                vendedor_id: token.owner_id.clone(),
+                comprador_id: receiver_id.clone(),
+                precio,
+            }
+            .emit();
+            token.precio_venta = Some(precio);
+        }
+
+        token.owner_id = receiver_id.clone();
+        token.modified_at = env::block_timestamp() / 1_000_000;
+        token.approvals.clear();
+        token.metadata_token.nombre_titular = None;
+        self.insertar_token(&token);
+        Ok(())
+    }
+
+    /// Aprobar un token por lote
+    /// Cada token se aprueba para el market `account_id` con su propio `min_precio`,
+    /// y cada market recibe un solo `batch_on_approve` con sus tokens
+    pub fn aprobar_por_lote(&mut self, tokens: Vec<(TokenId, ValidAccountId, U128)>) -> Promise {
         if tokens.len() > 10 {
             Panic::ExceedTokensToBatchApprove.panic();
         }
 
         let owner_id = env::predecessor_account_id();
-        let mut oks = Vec::new();
+        let mut oks: Vec<(AccountId, Vec<(TokenId, MarketApproveMsg)>)> = Vec::new();
         let mut errs = Vec::new();
-        for (token_id, min_precio) in tokens {
+        for (token_id, account_id, min_precio) in tokens {
             match self.aprobar_token(token_id, &owner_id, account_id.to_string(), min_precio) {
-                Ok(msg) => oks.push((token_id, msg)),
+                Ok(msg) => agrupar_por_market(&mut oks, account_id.as_ref(), (token_id, msg)),
                 Err(err) => errs.push((token_id, err)),
             }
         }
-        core_nft::nep178::market::batch_on_approve(
-            oks,
+        if oks.is_empty() {
+            Panic::Errors { panics: Panics(errs) }.panic();
+        }
+
+        let gas = env::prepaid_gas() / 3 / oks.len() as u64;
+        let mut aprobaciones: Option<Promise> = None;
+        for (market_id, msgs) in oks {
+            let aprobacion = core_nft::nep178::market::batch_on_approve(
+                msgs,
+                owner_id.clone().try_into().unwrap(),
+                &market_id,
+                NO_DEPOSIT,
+                gas,
+            );
+            aprobaciones = Some(match aprobaciones {
+                None => aprobacion,
+                Some(anteriores) => anteriores.and(aprobacion),
+            });
+        }
+        aprobaciones.unwrap().then(self_callback::resolve_batch_approve(
+            errs,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            env::prepaid_gas() / 3,
+        ))
+    }
+
+    /// Transferir tokens por lote
+    /// Los markets aprobados para los tokens transferidos
+    /// reciben un solo `batch_on_revoke` con sus tokens
+    pub fn nft_batch_transfer(&mut self, tokens: Vec<(ValidAccountId, TokenId)>) -> Promise {
+        if tokens.len() > 10 {
+            Panic::ExceedTokensToBatchTransfer.panic();
+        }
+
+        let sender_id = env::predecessor_account_id();
+        let mut revocados = Vec::new();
+        let mut errs = Vec::new();
+        for (receiver_id, token_id) in tokens {
+            let markets = self
+                .tokens
+                .get(&token_id)
+                .map_or(Vec::new(), |token| token.approvals.keys().cloned().collect());
+            match self.transferir_token(
+                &sender_id,
+                receiver_id.as_ref(),
+                token_id,
+                None,
+                None,
+                None,
+            ) {
+                Ok(()) => {
+                    for market_id in markets {
+                        agrupar_por_market(&mut revocados, &market_id, token_id);
+                    }
+                }
+                Err(err) => errs.push((token_id, err)),
+            }
+        }
+        self.notificar_revocados(revocados, errs)
+    }
+
+    /// Revocar la aprobación de `account_id` por lote
+    /// `account_id` recibe un solo `batch_on_revoke` con los tokens revocados
+    pub fn revocar_por_lote(
+        &mut self,
+        token_ids: Vec<TokenId>,
+        account_id: ValidAccountId,
+    ) -> Promise {
+        if token_ids.len() > 10 {
+            Panic::ExceedTokensToBatchRevoke.panic();
+        }
+
+        let owner_id = env::predecessor_account_id();
+        let mut revocados = Vec::new();
+        let mut errs = Vec::new();
+        for token_id in token_ids {
+            match self.revocar_token(token_id, &owner_id, account_id.as_ref()) {
+                Ok(()) => agrupar_por_market(&mut revocados, account_id.as_ref(), token_id),
+                Err(err) => errs.push((token_id, err)),
+            }
+        }
+        self.notificar_revocados(revocados, errs)
+    }
+
+    /// Actualiza el `min_precio` de la aprobación de `account_id` sobre `token_id`
+    /// y notifica al market aprobado para que actualice el precio de su listado.
+    /// Si el market rechaza la actualización, se restablece el precio anterior,
+    /// ver `resolve_price_update`.
+    /// Puede ejecutarse solo por el dueño del token.
+    pub fn actualizar_precio_aprobacion(
+        &mut self,
+        token_id: TokenId,
+        account_id: ValidAccountId,
+        min_precio: U128,
+    ) -> Promise {
+        let owner_id = env::predecessor_account_id();
+        let mut token = self.get_token_int(token_id);
+        if owner_id != token.owner_id {
+            Panic::TokenIdNotOwnedBy { token_id, owner_id }.panic();
+        }
+        let (aprobados_id, anterior) = match token.approvals.get_mut(account_id.as_ref()) {
+            None => Panic::ApprovalNotFound { account_id: account_id.into() }.panic(),
+            Some(approval) => {
+                let anterior = approval.min_precio;
+                approval.min_precio = min_precio;
+                (approval.aprobados_id, anterior)
+            }
+        };
+        self.tokens.insert(&token_id, &token);
+
+        core_nft::nep178::market::nft_on_price_update(
+            token_id,
             owner_id.try_into().unwrap(),
+            aprobados_id,
+            min_precio,
             account_id.as_ref(),
-            NO_DEPOSIT,
-            GAS_FOR_ROYALTIES,
+            0,
+            env::prepaid_gas() / 3,
         )
-        .then(self_callback::resolve_batch_approve(
-            errs,
+        .then(self_callback::resolve_price_update(
+            token_id,
+            account_id.into(),
+            aprobados_id,
+            min_precio,
+            anterior,
             &env::current_account_id(),
             NO_DEPOSIT,
-            GAS_FOR_ROYALTIES,
+            env::prepaid_gas() / 3,
         ))
     }
 
+    fn revocar_token(
+        &mut self,
+        token_id: TokenId,
+        owner_id: &AccountId,
+        account_id: &AccountId,
+    ) -> Result<(), Panic> {
+        let mut token = match self.tokens.get(&token_id) {
+            None => return Err(Panic::TokenIdNotFound { token_id }),
+            Some(token) => token,
+        };
+        if owner_id != &token.owner_id {
+            return Err(Panic::TokenIdNotOwnedBy { token_id, owner_id: owner_id.clone() });
+        }
+        if token.approvals.remove(account_id).is_none() {
+            return Err(Panic::RevokeApprovalFailed { account_id: account_id.clone() });
+        }
+        self.tokens.insert(&token_id, &token);
+        Ok(())
+    }
+
+    /// Notifica a cada market los tokens que ya no tiene aprobados con un solo `batch_on_revoke`,
+    /// y luego reporta los errores del lote en `resolve_batch_approve`.
+    fn notificar_revocados(
+        &self,
+        revocados: Vec<(AccountId, Vec<TokenId>)>,
+        errs: Vec<(TokenId, Panic)>,
+    ) -> Promise {
+        let markets = revocados.iter().map(|(market_id, _)| market_id.clone()).collect();
+        let resolver = self_callback::resolve_batch_revoke(
+            markets,
+            errs,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            env::prepaid_gas() / 3,
+        );
+        if revocados.is_empty() {
+            return resolver;
+        }
+
+        let gas = env::prepaid_gas() / 3 / revocados.len() as u64;
+        let mut notificaciones: Option<Promise> = None;
+        for (market_id, token_ids) in revocados {
+            let notificacion =
+                core_nft::nep178::market::batch_on_revoke(token_ids, &market_id, NO_DEPOSIT, gas);
+            notificaciones = Some(match notificaciones {
+                None => notificacion,
+                Some(anteriores) => anteriores.and(notificacion),
+            });
+        }
+        notificaciones.unwrap().then(resolver)
+    }
+
     fn aprobar_token(
         &mut self,
         token_id: TokenId,
@@ -420,11 +1149,67 @@ impl ContratoNft {
                 min_precio,
                 gate_id: Some(token.gate_id.try_into().unwrap()),
                 id_creador: Some(ticket.id_creador),
+                oferta_id: None,
+                expira_en: None,
+                aprobados_id: Some(token.approval_counter),
             }),
         }
     }
 }
 
+/// Migraciones del estado, ver `migraciones`
+#[near_bindgen]
+impl ContratoNft {
+    /// Comienza a llevar el estado desplegado al formato actual.
+    /// Solo convierte el struct del contrato, sus tickets y tokens se migran con `migrar_estado`.
+    /// El contrato no debe usarse hasta que `migrar_estado` termine.
+    #[init(ignore_state)]
+    pub fn iniciar_migracion() -> Self {
+        let anterior: ContratoNftBase = env::state_read().expect("Contract state not found");
+        assert_admin(&anterior.id_admin);
+
+        Self {
+            tickets: releer(&anterior.tickets),
+            tickets_de_creador: anterior.tickets_de_creador,
+            tokens: releer(&anterior.tokens),
+            tokens_de_address: anterior.tokens_de_address,
+            tokens_por_ticket: LookupMap::new(Keys::TokensPorTicket),
+            siguiente_token_id: 0,
+            pagos_holandes: LookupMap::new(Keys::PagosHolandes),
+            migracion: Some(Migracion::default()),
+            id_admin: anterior.id_admin,
+            metadata: anterior.metadata,
+            fee_reventa: anterior.fee_reventa,
+            fee_reventa_id_address: anterior.fee_reventa_id_address,
+        }
+    }
+
+    /// Migra hasta `limite` tokens creados, tickets y tokens, desde el paso `desde`.
+    /// `desde` es `0` en la primera llamada y luego el paso retornado por la llamada anterior.
+    /// Cada ticket pasa a guardar su cantidad como `u32` y sus tokens en `tokens_por_ticket`,
+    /// cada token se reescribe con `precio_venta` y `metadata_token` con su edicion,
+    /// y `siguiente_token_id` queda con el mayor ID de los tokens existentes mas uno.
+    /// Retorna el paso desde el que se debe continuar, o `None` si la migracion termino.
+    pub fn migrar_estado(&mut self, desde: U64, limite: u32) -> Option<U64> {
+        assert_admin(&self.id_admin);
+        let mut migracion = match self.migracion.take() {
+            None => Panic::MigrationNotStarted.panic(),
+            Some(migracion) => migracion,
+        };
+        if migracion.pasos != desde.0 {
+            Panic::MigrationStepMismatch { desde, pasos: migracion.pasos.into() }.panic();
+        }
+
+        if migrar(self, &mut migracion, limite.into()) {
+            None
+        } else {
+            let pasos = migracion.pasos.into();
+            self.migracion = Some(migracion);
+            Some(pasos)
+        }
+    }
+}
+
 /// Implementacion Token no fungible según NEP 171
 #[near_log(skip_args, only_pub)]
 #[near_bindgen]
@@ -437,35 +1222,16 @@ impl NonFungibleTokenCore for ContratoNft {
         memo: Option<String>,
     ) {
         let sender_id = env::predecessor_account_id();
-        let mut token = self.get_token_int(token_id);
-
-        if sender_id != token.owner_id && token.approvals.get(&sender_id).is_none() {
-            Panic::SenderNotAuthToTransfer { sender_id }.panic();
-        }
-
-        if &token.owner_id == receiver_id.as_ref() {
-            Panic::ReceiverIsOwner.panic();
-        }
-
-        if let Some(enforce_approval_id) = enforce_approval_id {
-            let TokenApproval { aprobados_id, min_precio: _ } = token
-                .approvals
-                .get(receiver_id.as_ref())
-                .expect("Receiver not an approver of this token.");
-            if aprobados_id != &enforce_approval_id {
-                Panic::EnforceApprovalFailed.panic();
-            }
-        }
-        if let Some(memo) = memo {
-            log!("Memo: {}", memo);
+        if let Err(err) = self.transferir_token(
+            &sender_id,
+            receiver_id.as_ref(),
+            token_id,
+            enforce_approval_id,
+            memo,
+            None,
+        ) {
+            err.panic();
         }
-
-        self.borrar_token_int(token_id, &token.owner_id);
-
-        token.owner_id = receiver_id.as_ref().to_string();
-        token.modified_at = env::block_timestamp() / 1_000_000;
-        token.approvals.clear();
-        self.insertar_token(&token);
     }
 
     fn nft_payout(&self, token_id: TokenId, balance: U128) -> Payout {
@@ -499,8 +1265,18 @@ impl NonFungibleTokenCore for ContratoNft {
         memo: Option<String>,
         balance: Option<U128>,
     ) -> Option<Payout> {
+        let sender_id = env::predecessor_account_id();
         let payout = balance.map(|balance| self.nft_payout(token_id, balance));
-        self.nft_transfer(receiver_id, token_id, aprobados_id, memo);
+        if let Err(err) = self.transferir_token(
+            &sender_id,
+            receiver_id.as_ref(),
+            token_id,
+            aprobados_id,
+            memo,
+            balance,
+        ) {
+            err.panic();
+        }
         payout
     }
 
@@ -528,10 +1304,10 @@ impl NonFungibleTokenApprovalMgmt for ContratoNft {
         account_id: ValidAccountId,
         msg: Option<String>,
     ) -> Promise {
-        let min_precio = {
+        let NftApproveMsg { min_precio, oferta_id, expira_en } = {
             if let Some(msg) = msg.clone() {
                 match serde_json::from_str::<NftApproveMsg>(&msg) {
-                    Ok(approve_msg) => approve_msg.min_precio,
+                    Ok(approve_msg) => approve_msg,
                     Err(err) => Panic::MsgFormatMinPriceMissing { reason: err.to_string() }.panic(),
                 }
             } else {
@@ -562,6 +1338,9 @@ impl NonFungibleTokenApprovalMgmt for ContratoNft {
                     min_precio,
                     gate_id: Some(token.gate_id.try_into().unwrap()),
                     id_creador: Some(ticket.id_creador),
+                    oferta_id,
+                    expira_en,
+                    aprobados_id: None,
                 };
                 core_nft::nep178::market::nft_on_approve(
                     token_id,
@@ -623,7 +1402,7 @@ impl NonFungibleTokenEnumeration for ContratoNft {
         while result.len() < limit.unwrap_or(u32::MAX) as usize {
             if let Some(mut token) = self.tokens.values_as_vector().get(i) {
                 let ticket = self.tickets.get(&token.gate_id).expect("Gate id not found");
-                token.metadata = ticket.metadata;
+                token.metadata = ticket.metadata.con_token(&token.metadata_token);
                 result.push(token);
                 i += 1
             } else {
@@ -679,11 +1458,192 @@ impl NonFungibleTokenEnumeration for ContratoNft {
 
 const GAS_FOR_ROYALTIES: Gas = 120_000_000_000_000;
 const NO_DEPOSIT: Balance = 0;
+const MAX_HISTORIAL_METADATA: usize = 10;
+const ESQUEMAS_URL: [&str; 3] = ["ipfs://", "ar://", "https://"];
+
+/// Agrega `item` al grupo de `market_id` en `grupos`, creando el grupo si no existe.
+fn agrupar_por_market<T>(grupos: &mut Vec<(AccountId, Vec<T>)>, market_id: &AccountId, item: T) {
+    match grupos.iter_mut().find(|(id, _)| id == market_id) {
+        Some((_, items)) => items.push(item),
+        None => grupos.push((market_id.clone(), vec![item])),
+    }
+}
+
+/// Valida el largo de los campos de `metadata` del ticket `gate_id`,
+/// el esquema de sus URLs y que cada URL tenga su hash.
+fn validar_metadata(gate_id: &GateId, metadata: &Metadata) {
+    let gate_id = gate_id.clone();
+    if metadata.titulo.as_ref().map_or(false, |titulo| titulo.len() > 140) {
+        Panic::InvalidArgument {
+            gate_id,
+            reason: "Titulo no puede tener mas de 140 caracteres".to_string(),
+        }
+        .panic();
+    }
+    if metadata.descripcion.as_ref().map_or(false, |descripcion| descripcion.len() > 1024) {
+        Panic::InvalidArgument {
+            gate_id,
+            reason: "`La descripcion no puede sobrepasar los 1024 caracteres".to_string(),
+        }
+        .panic();
+    }
+
+    macro_rules! check {
+        ($arg:ident) => {{
+            if let Some(val) = &metadata.$arg {
+                if val.len() > 1024 {
+                    Panic::InvalidArgument {
+                        gate_id,
+                        reason: concat!("`", stringify!($arg), "` exceeds 1024 chars").to_string(),
+                    }
+                    .panic();
+                }
+            }
+        }};
+    }
+
+    check!(media);
+    check!(media_hash);
+    check!(referencia);
+    check!(referencia_hash);
+
+    macro_rules! check_url {
+        ($url:ident, $hash:ident) => {{
+            if let Some(hash) = &metadata.$hash {
+                if base64::decode(hash).map_or(true, |hash| hash.len() != 32) {
+                    let reason =
+                        concat!("`", stringify!($hash), "` is not a base64-encoded sha256");
+                    Panic::InvalidArgument { gate_id, reason: reason.to_string() }.panic();
+                }
+            }
+            if let Some(url) = &metadata.$url {
+                if !ESQUEMAS_URL.iter().any(|esquema| url.starts_with(esquema)) {
+                    let reason =
+                        concat!("`", stringify!($url), "` must be an ipfs, ar or https URL");
+                    Panic::InvalidArgument { gate_id, reason: reason.to_string() }.panic();
+                }
+                if metadata.$hash.is_none() {
+                    let reason = concat!(
+                        "`",
+                        stringify!($hash),
+                        "` is required when `",
+                        stringify!($url),
+                        "` is given"
+                    );
+                    Panic::InvalidArgument { gate_id, reason: reason.to_string() }.panic();
+                }
+            }
+        }};
+    }
+
+    check_url!(media, media_hash);
+    check_url!(referencia, referencia_hash);
+}
+
+/// Valida los `detalles` del evento del ticket `gate_id` creado en `ahora`.
+fn validar_detalles(gate_id: &GateId, detalles: &DetallesEvento, ahora: Timestamp) {
+    let invalido = |reason: &str| {
+        Panic::InvalidArgument { gate_id: gate_id.clone(), reason: reason.to_string() }.panic()
+    };
+    if detalles.lugar.is_empty() || detalles.lugar.len() > 140 {
+        invalido("`lugar` debe tener entre 1 y 140 caracteres");
+    }
+    if detalles.direccion.as_ref().map_or(false, |direccion| direccion.len() > 256) {
+        invalido("`direccion` no puede tener mas de 256 caracteres");
+    }
+    if let Some(Coordenadas { latitud, longitud }) = detalles.coordenadas {
+        if !(-90.0..=90.0).contains(&latitud) {
+            invalido("`latitud` debe estar entre -90 y 90");
+        }
+        if !(-180.0..=180.0).contains(&longitud) {
+            invalido("`longitud` debe estar entre -180 y 180");
+        }
+    }
+    if detalles.apertura_puertas_en.map_or(false, |apertura| apertura <= ahora) {
+        invalido("`apertura_puertas_en` debe ser posterior al momento actual");
+    }
+    if detalles.edad_minima.map_or(false, |edad| edad > 99) {
+        invalido("`edad_minima` no puede superar los 99 años");
+    }
+}
+
+/// Guarda el hash de la metadata actual de `ticket` en su `historial_metadata`.
+fn registrar_metadata(ticket: &mut Collectible, ahora: Timestamp) {
+    let registro = RegistroMetadata { hash: ticket.metadata.hash(), actualizado_en: ahora };
+    ticket.historial_metadata.insert(0, registro);
+    ticket.historial_metadata.truncate(MAX_HISTORIAL_METADATA);
+}
+
+/// Da un Panic error si `url` reemplaza a la URL `anterior` sin dar su nuevo `hash`,
+/// asi el hash guardado siempre corresponde al contenido de la URL.
+fn validar_cambio_url(
+    gate_id: &GateId,
+    nombre: &str,
+    anterior: &Option<String>,
+    url: &Option<String>,
+    hash: &Option<String>,
+) {
+    if url.is_some() && url != anterior && hash.is_none() {
+        Panic::InvalidArgument {
+            gate_id: gate_id.clone(),
+            reason: format!("`{}` changed without a new `{}_hash`", nombre, nombre),
+        }
+        .panic();
+    }
+}
+
+/// Valida el largo de un campo `nombre` de la metadata de un token del ticket `gate_id`.
+fn validar_metadata_token(gate_id: &GateId, nombre: &str, valor: &Option<String>) {
+    if valor.as_ref().map_or(false, |valor| valor.len() > 140) {
+        Panic::InvalidArgument {
+            gate_id: gate_id.clone(),
+            reason: format!("`{}` no puede tener mas de 140 caracteres", nombre),
+        }
+        .panic();
+    }
+}
+
+/// Retorna la cantidad sin vender de `ticket` para cambiarla en `cantidad`,
+/// o da un Panic error si el ticket es una edicion abierta, esta cancelado
+/// o su venta holandesa ya vendio tokens, ya que cambiaria su precio final.
+fn get_cantidad_actual(ticket: &Collectible, cantidad: u32) -> u32 {
+    let gate_id = ticket.gate_id.clone();
+    if ticket.cancelado {
+        Panic::GateIdCancelled { gate_id }.panic();
+    }
+    if ticket.venta_holandesa.as_ref().map_or(false, |venta| venta.vendidos > 0) {
+        Panic::DutchAuctionStarted { gate_id }.panic();
+    }
+    if cantidad == 0 {
+        Panic::ZeroSupplyNotAllowed { gate_id }.panic();
+    }
+    match ticket.cantidad_actual {
+        None => Panic::OpenEditionSupply { gate_id }.panic(),
+        Some(cantidad_actual) => cantidad_actual,
+    }
+}
 
 #[near_ext]
 #[ext_contract(self_callback)]
 trait SelfCallback {
+    /// Reporta los errores de un metodo por lote
+    /// una vez resueltas las llamadas a los markets.
     fn resolve_batch_approve(&mut self, errs: Vec<(TokenId, Panic)>);
+
+    /// Reporta los errores de `batch_revoke` una vez notificados los `markets`.
+    /// Las revocaciones ya se aplicaron, por lo que una notificación fallida solo se registra.
+    fn resolve_batch_revoke(&mut self, markets: Vec<AccountId>, errs: Vec<(TokenId, Panic)>);
+
+    /// Restablece el `min_precio` `anterior` de la aprobación de `account_id`
+    /// si el market no aceptó el cambio a `min_precio`.
+    fn resolve_price_update(
+        &mut self,
+        token_id: TokenId,
+        account_id: AccountId,
+        aprobados_id: U64,
+        min_precio: U128,
+        anterior: U128,
+    );
 }
 
 #[near_log(skip_args, only_pub)]
@@ -691,12 +1651,59 @@ trait SelfCallback {
 impl SelfCallback for ContratoNft {
     #[private]
     fn resolve_batch_approve(&mut self, errs: Vec<(TokenId, Panic)>) {
+        for i in 0..env::promise_results_count() {
+            match env::promise_result(i) {
+                PromiseResult::NotReady => unreachable!(),
+                PromiseResult::Failed => unreachable!(),
+                PromiseResult::Successful(_) => {}
+            }
+        }
+        if !errs.is_empty() {
+            Panic::Errors { panics: Panics(errs) }.panic()
+        }
+    }
+
+    #[private]
+    fn resolve_batch_revoke(&mut self, markets: Vec<AccountId>, errs: Vec<(TokenId, Panic)>) {
+        for i in 0..env::promise_results_count() {
+            if let PromiseResult::Failed = env::promise_result(i) {
+                log!("Market `{}` failed to process the revocations", markets[i as usize]);
+            }
+        }
+        if !errs.is_empty() {
+            Panic::Errors { panics: Panics(errs) }.panic()
+        }
+    }
+
+    #[private]
+    fn resolve_price_update(
+        &mut self,
+        token_id: TokenId,
+        account_id: AccountId,
+        aprobados_id: U64,
+        min_precio: U128,
+        anterior: U128,
+    ) {
         match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
-            PromiseResult::Failed => unreachable!(),
-            PromiseResult::Successful(_) => {
-                if !errs.is_empty() {
-                    Panic::Errors { panics: Panics(errs) }.panic()
+            PromiseResult::Successful(_) => {}
+            PromiseResult::Failed => {
+                let mut token = match self.tokens.get(&token_id) {
+                    None => return,
+                    Some(token) => token,
+                };
+                // Solo si la aprobación no cambió desde la actualización.
+                if let Some(approval) = token.approvals.get_mut(&account_id) {
+                    if approval.aprobados_id == aprobados_id && approval.min_precio == min_precio {
+                        log!(
+                            "Market `{}` rejected the price of token {}, restoring {}",
+                            account_id,
+                            token_id.0,
+                            anterior.0
+                        );
+                        approval.min_precio = anterior;
+                        self.tokens.insert(&token_id, &token);
+                    }
                 }
             }
         }
@@ -725,6 +1732,28 @@ pub enum Panic {
     GateIdNotFound { gate_id: GateId },
     #[panic_msg = "Tokens for gate id `{}` have already been claimed"]
     GateIdExhausted { gate_id: GateId },
+    #[panic_msg = "Tokens of gate ID `{}` can no longer be minted"]
+    MintingEnded { gate_id: GateId },
+    #[panic_msg = "Gate ID `{}` is an open edition without a fixed supply"]
+    OpenEditionSupply { gate_id: GateId },
+    #[panic_msg = "Supply of gate ID `{}` cannot change once its Dutch auction has sold tokens"]
+    DutchAuctionStarted { gate_id: GateId },
+    #[panic_msg = "Supply of gate ID `{}` cannot be less than its `{}` minted tokens"]
+    SupplyBelowMinted { gate_id: GateId, cantidad_creada: u32 },
+    #[panic_msg = "Gate ID `{}` has been cancelled"]
+    GateIdCancelled { gate_id: GateId },
+    #[panic_msg = "Tokens of gate ID `{}` can be burned only once it is cancelled or expired"]
+    GateIdNotBurnable { gate_id: GateId },
+    #[panic_msg = "Sale for gate ID `{}` has not started yet"]
+    SaleNotStarted { gate_id: GateId },
+    #[panic_msg = "Not enough deposit to buy gate ID `{}` at `{:?}`"]
+    NotEnoughDeposit { gate_id: GateId, precio: U128 },
+    #[panic_msg = "Gate ID `{}` has no rebate available"]
+    NoRebateAvailable { gate_id: GateId },
+    #[panic_msg = "Clearing price for gate ID `{}` is not known yet"]
+    ClearingPriceNotReached { gate_id: GateId },
+    #[panic_msg = "Operation on gate ID `{}` is allowed only for its creator"]
+    CreatorRestrictedOperation { gate_id: GateId },
     #[panic_msg = "Gate ID `{}` has already some claimed tokens"]
     GateIdHasTokens { gate_id: GateId },
     #[panic_msg = "Unable to delete gate ID `{}`"]
@@ -733,22 +1762,38 @@ pub enum Panic {
     TokenIdNotFound { token_id: U64 },
     #[panic_msg = "Token ID `{:?}` does not belong to account `{}`"]
     TokenIdNotOwnedBy { token_id: U64, owner_id: AccountId },
+    #[panic_msg = "Token ID `{:?}` does not belong to gate ID `{}`"]
+    TokenNotOfGate { token_id: U64, gate_id: GateId },
     #[panic_msg = "At most one approval is allowed per Token"]
     OneApprovalAllowed,
     #[panic_msg = "Sender `{}` is not authorized to make transfer"]
     SenderNotAuthToTransfer { sender_id: AccountId },
     #[panic_msg = "The token owner and the receiver should be different"]
     ReceiverIsOwner,
-    #[panic_msg = "The aprobados_id is different from enforce_approval_id"]
-    EnforceApprovalFailed,
+    #[panic_msg = "Approval ID `{:?}` of `{}` is different from enforce_approval_id `{:?}`"]
+    EnforceApprovalFailed { aprobados_id: U64, sender_id: AccountId, enforce_approval_id: U64 },
+    #[panic_msg = "Balance `{:?}` is below the minimum price `{:?}` of token `{:?}`"]
+    PriceBelowMinimum { balance: U128, min_precio: U128, token_id: TokenId },
     #[panic_msg = "The msg argument must contain the minimum price"]
     MsgFormatNotRecognized,
     #[panic_msg = "Could not find min_precio in msg: {}"]
     MsgFormatMinPriceMissing { reason: String },
+    #[panic_msg = "Account `{}` is not approved for this token"]
+    ApprovalNotFound { account_id: AccountId },
     #[panic_msg = "Could not revoke approval for `{}`"]
     RevokeApprovalFailed { account_id: AccountId },
     #[panic_msg = "At most 10 tokens are allowed to approve in batch"]
     ExceedTokensToBatchApprove,
+    #[panic_msg = "At most 10 tokens are allowed to transfer in batch"]
+    ExceedTokensToBatchTransfer,
+    #[panic_msg = "At most 10 tokens are allowed to revoke in batch"]
+    ExceedTokensToBatchRevoke,
+    #[panic_msg = "At most 50 tokens are allowed to burn in batch"]
+    ExceedTokensToBatchBurn,
+    #[panic_msg = "There is no state migration in progress"]
+    MigrationNotStarted,
+    #[panic_msg = "Migration step `{:?}` is different from the current step `{:?}`"]
+    MigrationStepMismatch { desde: U64, pasos: U64 },
     #[panic_msg = "{} error(s) detected, see `panics` fields for a full list of errors"]
     Errors { panics: Panics },
 }
@@ -1,25 +1,47 @@
-use std::{convert::TryInto, fmt::{Debug, Display},};
+use std::{cmp::Ordering, convert::TryInto, fmt::{Debug, Display},};
 pub use crate::core_nft::{
     crypto_hash,
     gate::{GateId, ValidGateId},
     nep178::NonFungibleTokenApprovalsReceiver,
     nep171,
-    MarketApproveMsg, Payout, TokenId,
+    MarketApproveMsg, Payout, Token, TokenId,
 };
+use crate::core_nft::{fraction::Fraction, Timestamp};
 use near_env::{near_ext, near_log, PanicMessage};
 use near_sdk::{
     borsh::{self, BorshDeserialize, BorshSerialize},
-    collections::{LookupMap, UnorderedMap, UnorderedSet},
+    collections::{LookupMap, TreeMap, UnorderedMap, UnorderedSet},
     env, ext_contract,
     json_types::{ValidAccountId, U128, U64},
-    near_bindgen,
+    log, near_bindgen,
     serde::{Deserialize, Serialize},
     serde_json, AccountId, Balance, BorshStorageKey, CryptoHash, Gas, PanicOnDefault,
     Promise, PromiseResult,
 };
 
 const GAS_FOR_ROYALTIES: Gas = 120_000_000_000_000;
+const GAS_FOR_NFT_TOKEN: Gas = 10_000_000_000_000;
+const GAS_FOR_RELISTAR: Gas = 20_000_000_000_000;
+/// Gas minimo de cada `nft_transfer_payout` en `buy_tokens`.
+const GAS_FOR_NFT_TRANSFER_PAYOUT: Gas = 20_000_000_000_000;
+/// Gas que `buy_tokens` reserva para crear cada promesa luego de validar el gas.
+const GAS_FOR_BUY_TOKENS: Gas = 15_000_000_000_000;
+/// Gas base de `resolver_compra_por_lote`, al que se suma `GAS_POR_COMPRA_EN_LOTE` por token.
+const GAS_FOR_RESOLVER_LOTE: Gas = 10_000_000_000_000;
+/// Gas que `resolver_compra_por_lote` necesita por token:
+/// liquidar la compra y, si la transferencia falla, llamar a `nft_token` y `relistar`.
+const GAS_POR_COMPRA_EN_LOTE: Gas = 5_000_000_000_000 + GAS_FOR_NFT_TOKEN + GAS_FOR_RELISTAR;
 const NO_DEPOSIT: Balance = 0;
+/// Una puja en los ultimos 10 minutos extiende la subasta hasta 10 minutos despues de la puja.
+const EXTENSION_SUBASTA: Timestamp = 10 * 60 * 1000;
+/// Cantidad maxima de tokens que se pueden comprar en `buy_tokens`, de modo que el gas
+/// de las transferencias y de `resolver_compra_por_lote` entre en una sola transacción.
+const MAX_TOKENS_POR_LOTE: usize = 3;
+/// Cantidad de ventas que se guardan en el historial de cada token y de cada `GateId`.
+const MAX_HISTORIAL_VENTAS: usize = 20;
+/// Cantidad maxima de tokens que retorna una consulta de tokens en venta,
+/// se usa tambien cuando la consulta no indica `limit`.
+const MAX_TOKENS_POR_CONSULTA: u32 = 100;
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
@@ -29,6 +51,27 @@ pub struct ContratoMercado {
     tokens_por_id_gate: LookupMap<GateId, UnorderedSet<TokenKey>>,
     tokens_por_id_owner: LookupMap<AccountId, UnorderedSet<TokenKey>>,
     tokens_por_id_creador: LookupMap<AccountId, UnorderedSet<TokenKey>>,
+    /// Tokens en venta de cada `GateId` ordenados por `min_precio`.
+    libro_por_gate: LookupMap<GateId, TreeMap<(Balance, TokenKey), ()>>,
+    ofertas: UnorderedMap<OfertaId, Oferta>,
+    ofertas_por_token: LookupMap<String, UnorderedSet<OfertaId>>,
+    ofertas_por_gate: LookupMap<GateId, UnorderedSet<OfertaId>>,
+    ofertas_por_bidder: LookupMap<AccountId, UnorderedSet<OfertaId>>,
+    ultima_oferta_id: u64,
+    subastas: UnorderedMap<TokenKey, Subasta>,
+
+    owner_id: AccountId,
+    /// Porcentaje de cada venta que se queda el market.
+    fee_mercado: Fraction,
+    /// Fees cobrados y todavia no retirados por `owner_id`.
+    fees_acumulados: Balance,
+    /// Contratos NFT cuyos tokens se pueden poner en venta.
+    contratos_nft: UnorderedSet<AccountId>,
+    /// Ultimas ventas de cada token, indexadas por `TokenKey.to_string()`.
+    ventas_por_token: LookupMap<String, Vec<RegistroVenta>>,
+    /// Ultimas ventas de cada `GateId`.
+    ventas_por_gate: LookupMap<GateId, Vec<RegistroVenta>>,
+    estadisticas_por_gate: LookupMap<GateId, EstadisticasVentas>,
 }
 
 /// Cada token debe estar identificado por `<nft contract id, token id>`.
@@ -36,6 +79,27 @@ pub struct ContratoMercado {
 #[serde(crate = "near_sdk::serde")]
 pub struct TokenKey(AccountId, TokenId);
 
+/// `TokenId` no implementa `Ord`, por lo que se ordena por `<nft contract id, token id.0>`.
+impl Ord for TokenKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.0, self.1 .0).cmp(&(&other.0, other.1 .0))
+    }
+}
+
+impl PartialOrd for TokenKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for TokenKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TokenKey {}
+
 impl Display for TokenKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}:{:?}", self.0, self.1)
@@ -43,8 +107,8 @@ impl Display for TokenKey {
 }
 
 /// Estructura que representa los tickets en venta
-#[derive(BorshDeserialize, BorshSerialize, Serialize)]
-#[cfg_attr(not(target_arch = "wasm"), derive(Debug, Deserialize))]
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm"), derive(Debug, Clone))]
 #[serde(crate = "near_sdk::serde")]
 pub struct TokenEnVenta {
     pub contrato_id: AccountId,
@@ -54,6 +118,130 @@ pub struct TokenEnVenta {
     pub min_precio: U128,
     pub gate_id: Option<GateId>,
     pub id_creador: Option<AccountId>,
+    /// UNIX epoch (en milisegundos) en que vence la venta, si vence.
+    pub expira_en: Option<Timestamp>,
+}
+
+impl TokenEnVenta {
+    /// Indica si la venta ya vencio en `ahora`.
+    pub fn vencido(&self, ahora: Timestamp) -> bool {
+        self.expira_en.map_or(false, |expira_en| ahora >= expira_en)
+    }
+}
+
+/// Venta completada en el market.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct RegistroVenta {
+    pub contrato_id: AccountId,
+    pub token_id: TokenId,
+    pub comprador_id: AccountId,
+    pub vendedor_id: AccountId,
+    /// Monto pagado por el comprador, incluido el fee del market.
+    pub precio: U128,
+    /// UNIX epoch (en milisegundos) de la venta.
+    pub vendido_en: Timestamp,
+}
+
+/// Estadisticas de todas las ventas de un `GateId` en el market.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct EstadisticasVentas {
+    pub cantidad: u64,
+    pub volumen: U128,
+    pub ultimo_precio: U128,
+    pub precio_promedio: U128,
+}
+
+/// `U128` no implementa `Default`, por lo que se inicializa a mano.
+impl Default for EstadisticasVentas {
+    fn default() -> Self {
+        Self { cantidad: 0, volumen: U128(0), ultimo_precio: U128(0), precio_promedio: U128(0) }
+    }
+}
+
+/// Orden por `min_precio` de las consultas de tokens en venta.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Orden {
+    Asc,
+    Desc,
+}
+
+/// Filtros opcionales de las consultas de tokens en venta.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FiltroVenta {
+    /// Solo tokens con `min_precio` mayor o igual.
+    pub precio_min: Option<U128>,
+    /// Solo tokens con `min_precio` menor o igual.
+    pub precio_max: Option<U128>,
+    /// Ordena los tokens por `min_precio`.
+    pub orden: Option<Orden>,
+}
+
+impl FiltroVenta {
+    /// Indica si `token` cumple con el rango de precios.
+    fn incluye(&self, token: &TokenEnVenta) -> bool {
+        self.precio_min.map_or(true, |min| token.min_precio.0 >= min.0)
+            && self.precio_max.map_or(true, |max| token.min_precio.0 <= max.0)
+    }
+}
+
+/// Identificador de una `Oferta`.
+pub type OfertaId = U64;
+
+/// Indica sobre qué se hace una `Oferta`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub enum ObjetivoOferta {
+    /// Un token puntual, este o no en venta.
+    Token { contrato_id: AccountId, token_id: TokenId },
+    /// Cualquier token del `gate_id` indicado.
+    Gate { contrato_id: AccountId, gate_id: GateId },
+}
+
+/// Oferta de compra de `bidder_id`, cuyo `monto` queda depositado en el market
+/// hasta que es aceptada o retirada.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Oferta {
+    pub oferta_id: OfertaId,
+    pub bidder_id: AccountId,
+    pub objetivo: ObjetivoOferta,
+    pub monto: U128,
+    pub creado_en: u64,
+}
+
+/// Puja de `bidder_id` en una `Subasta`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Puja {
+    pub bidder_id: AccountId,
+    pub monto: U128,
+}
+
+/// Subasta inglesa de un token en venta.
+/// Solo la `mejor_puja` queda depositada en el market, las superadas se reembolsan.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Subasta {
+    pub contrato_id: AccountId,
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    /// Monto minimo de la primer puja, es el `min_precio` del token en venta.
+    pub precio_reserva: U128,
+    /// Cuanto debe superar cada puja a la `mejor_puja`.
+    pub incremento_minimo: U128,
+    /// UNIX epoch (en milisegundos) en que termina la subasta.
+    pub fin_en: Timestamp,
+    pub mejor_puja: Option<Puja>,
 }
 
 #[derive(BorshSerialize, BorshStorageKey)]
@@ -67,76 +255,581 @@ enum Keys {
     TokensPorIdOwnerValor(CryptoHash),
     TokensPorIdCreador,
     TokensPorIdCreadorValor(CryptoHash),
+    Ofertas,
+    OfertasPorToken,
+    OfertasPorTokenValor(CryptoHash),
+    OfertasPorGate,
+    OfertasPorGateValor(CryptoHash),
+    OfertasPorBidder,
+    OfertasPorBidderValor(CryptoHash),
+    Subastas,
+    LibroPorGate,
+    LibroPorGateValor(CryptoHash),
+    ContratosNft,
+    VentasPorToken,
+    VentasPorGate,
+    EstadisticasPorGate,
 }
 
 /// Métodos del contrato market
 #[near_log(skip_args, only_pub)]
-// #[near_bindgen]
+#[near_bindgen]
 impl ContratoMercado {
     /// Inicializa el contrato
-    // #[init]
-    pub fn init() -> Self {
-        Self {
+    /// No se llama `init` porque el market se compila en el mismo wasm
+    /// que el contrato NFT, que ya exporta `init`
+    ///
+    /// `owner_id` administra el market y retira los fees
+    /// `fee_mercado` es el porcentaje de cada venta que se queda el market
+    /// `contratos_nft` son los contratos NFT cuyos tokens se pueden poner en venta
+    #[init]
+    pub fn init_mercado(
+        owner_id: ValidAccountId,
+        fee_mercado: Fraction,
+        contratos_nft: Vec<ValidAccountId>,
+    ) -> Self {
+        fee_mercado.check();
+
+        let mut this = Self {
             tokens_en_venta: UnorderedMap::new(Keys::TokensEnVenta),
             tokens_por_id: LookupMap::new(Keys::TokensPorID),
             tokens_por_id_gate: LookupMap::new(Keys::TokensPorIdGate),
             tokens_por_id_owner: LookupMap::new(Keys::TokensPorIdOwner),
             tokens_por_id_creador: LookupMap::new(Keys::TokensPorIdCreador),
+            libro_por_gate: LookupMap::new(Keys::LibroPorGate),
+            ofertas: UnorderedMap::new(Keys::Ofertas),
+            ofertas_por_token: LookupMap::new(Keys::OfertasPorToken),
+            ofertas_por_gate: LookupMap::new(Keys::OfertasPorGate),
+            ofertas_por_bidder: LookupMap::new(Keys::OfertasPorBidder),
+            ultima_oferta_id: 0,
+            subastas: UnorderedMap::new(Keys::Subastas),
+            owner_id: owner_id.into(),
+            fee_mercado,
+            fees_acumulados: 0,
+            contratos_nft: UnorderedSet::new(Keys::ContratosNft),
+            ventas_por_token: LookupMap::new(Keys::VentasPorToken),
+            ventas_por_gate: LookupMap::new(Keys::VentasPorGate),
+            estadisticas_por_gate: LookupMap::new(Keys::EstadisticasPorGate),
+        };
+        for contrato_id in contratos_nft {
+            this.contratos_nft.insert(contrato_id.as_ref());
         }
+        this
     }
 
-    /// Retorna todos los tokens en venta
-    pub fn get_tokens_en_venta(&self) -> Vec<TokenEnVenta> {
-        let mut result = Vec::new();
-        for (_, token) in self.tokens_en_venta.iter() {
-            result.push(token);
+    /// Permite poner en venta los tokens de `contrato_id`
+    /// Puede ejecutarse solo por `owner_id`
+    pub fn agregar_contrato_nft(&mut self, contrato_id: ValidAccountId) {
+        self.assert_owner();
+        self.contratos_nft.insert(contrato_id.as_ref());
+    }
+
+    /// Deja de aceptar nuevas ventas de tokens de `contrato_id`
+    /// Los tokens que ya estan en venta siguen en venta
+    /// Puede ejecutarse solo por `owner_id`
+    pub fn quitar_contrato_nft(&mut self, contrato_id: ValidAccountId) {
+        self.assert_owner();
+        self.contratos_nft.remove(contrato_id.as_ref());
+    }
+
+    /// Retorna los contratos NFT cuyos tokens se pueden poner en venta
+    pub fn get_contratos_nft(&self) -> Vec<AccountId> {
+        self.contratos_nft.to_vec()
+    }
+
+    /// Retorna la cantidad de tokens en venta de cada contrato NFT soportado
+    pub fn get_cantidad_por_contrato(&self) -> Vec<(AccountId, u64)> {
+        self.contratos_nft
+            .iter()
+            .map(|contrato_id| {
+                let cantidad = self.tokens_por_id.get(&contrato_id).map_or(0, |tids| tids.len());
+                (contrato_id, cantidad)
+            })
+            .collect()
+    }
+
+    /// Cambia el fee del market
+    /// Puede ejecutarse solo por `owner_id`
+    pub fn set_fee_mercado(&mut self, fee_mercado: Fraction) {
+        self.assert_owner();
+        fee_mercado.check();
+        self.fee_mercado = fee_mercado;
+    }
+
+    /// Transfiere a `owner_id` los fees acumulados y retorna el monto transferido
+    /// Puede ejecutarse solo por `owner_id`
+    pub fn retirar_fees(&mut self) -> U128 {
+        self.assert_owner();
+        let monto = self.fees_acumulados;
+        self.fees_acumulados = 0;
+        if monto > 0 {
+            Promise::new(self.owner_id.clone()).transfer(monto);
         }
-        result
+        U128(monto)
+    }
+
+    /// Retorna el fee del market
+    pub fn get_fee_mercado(&self) -> Fraction {
+        self.fee_mercado
     }
 
-    /// Retorna todos los tokens en venta para un `owner_id`
-    pub fn get_tokens_by_owner_id(&self, owner_id: ValidAccountId) -> Vec<TokenEnVenta> {
-        get_tokens_by(&self.tokens_en_venta, &self.tokens_por_id_owner, owner_id.as_ref())
+    /// Retorna los fees acumulados
+    pub fn get_fees_acumulados(&self) -> U128 {
+        U128(self.fees_acumulados)
     }
 
-    /// Retorna todos los tokens en venta para un `id_creador`
-    pub fn get_tokens_by_creator_id(&self, id_creador: ValidAccountId) -> Vec<TokenEnVenta> {
-        get_tokens_by(&self.tokens_en_venta, &self.tokens_por_id_creador, id_creador.as_ref())
+    /// Retorna los tokens en venta, paginados desde `from_index` hasta `limit` tokens
+    /// Las consultas aceptan un `filtro` opcional, ver `FiltroVenta`
+    pub fn get_tokens_en_venta(
+        &self,
+        from_index: Option<U64>,
+        limit: Option<u32>,
+        filtro: Option<FiltroVenta>,
+    ) -> Vec<TokenEnVenta> {
+        paginar(self.tokens_en_venta.values(), from_index, limit, filtro)
     }
 
-    // #[payable]
+    /// Retorna los tokens en venta para un `owner_id`
+    pub fn get_tokens_by_owner_id(
+        &self,
+        owner_id: ValidAccountId,
+        from_index: Option<U64>,
+        limit: Option<u32>,
+        filtro: Option<FiltroVenta>,
+    ) -> Vec<TokenEnVenta> {
+        get_tokens_by(
+            &self.tokens_en_venta,
+            &self.tokens_por_id_owner,
+            owner_id.as_ref(),
+            from_index,
+            limit,
+            filtro,
+        )
+    }
+
+    /// Retorna los tokens en venta para un `id_creador`
+    pub fn get_tokens_by_creator_id(
+        &self,
+        id_creador: ValidAccountId,
+        from_index: Option<U64>,
+        limit: Option<u32>,
+        filtro: Option<FiltroVenta>,
+    ) -> Vec<TokenEnVenta> {
+        get_tokens_by(
+            &self.tokens_en_venta,
+            &self.tokens_por_id_creador,
+            id_creador.as_ref(),
+            from_index,
+            limit,
+            filtro,
+        )
+    }
+
+    /// Retorna los tokens en venta para un `gate_id`
+    /// Con `orden` los tokens se leen del libro de precios del `gate_id`, ver `paginar_libro`.
+    pub fn get_tokens_by_gate_id(
+        &self,
+        gate_id: ValidGateId,
+        from_index: Option<U64>,
+        limit: Option<u32>,
+        filtro: Option<FiltroVenta>,
+    ) -> Vec<TokenEnVenta> {
+        if let Some(filtro) = filtro.as_ref().filter(|filtro| filtro.orden.is_some()) {
+            let libro = match self.libro_por_gate.get(gate_id.as_ref()) {
+                None => return Vec::new(),
+                Some(libro) => libro,
+            };
+            return self.paginar_libro(&libro, from_index, limit, filtro);
+        }
+        get_tokens_by(
+            &self.tokens_en_venta,
+            &self.tokens_por_id_gate,
+            gate_id.as_ref(),
+            from_index,
+            limit,
+            filtro,
+        )
+    }
+
+    /// Retorna los tokens en venta del contrato NFT `contrato_id`
+    pub fn get_tokens_by_contract_id(
+        &self,
+        contrato_id: ValidAccountId,
+        from_index: Option<U64>,
+        limit: Option<u32>,
+        filtro: Option<FiltroVenta>,
+    ) -> Vec<TokenEnVenta> {
+        match self.tokens_por_id.get(contrato_id.as_ref()) {
+            None => Vec::new(),
+            Some(tids) => paginar(
+                tids.iter().map(|token_id| {
+                    let token_key = TokenKey(contrato_id.to_string(), token_id);
+                    self.tokens_en_venta.get(&token_key).expect("Token not found")
+                }),
+                from_index,
+                limit,
+                filtro,
+            ),
+        }
+    }
+
+    #[payable]
     pub fn buy_token(&mut self, contrato_id: ValidAccountId, token_id: TokenId) {
         let token_key = TokenKey(contrato_id.to_string(), token_id);
-        if let Some(TokenEnVenta { owner_id, min_precio, gate_id, id_creador, .. }) =
-            self.tokens_en_venta.get(&token_key)
-        {
-            let buyer_id = env::predecessor_account_id();
-            if buyer_id == owner_id {
+        let token = self.get_token_comprable(&token_key);
+        self.comprar(token_key, token);
+    }
+
+    /// Compra varios tokens en venta en una sola transacción.
+    /// A cada token se le asigna del deposito adjunto su `min_precio` mas el fee del market,
+    /// y lo que sobra se reembolsa en el momento.
+    /// Si la transferencia de algun token falla, se reembolsa lo asignado a ese token,
+    /// ver `resolver_compra_por_lote`.
+    /// El gas adjunto debe cubrir el peor caso en que fallan todas las transferencias.
+    #[payable]
+    pub fn buy_tokens(&mut self, tokens: Vec<(ValidAccountId, TokenId)>) {
+        if tokens.len() > MAX_TOKENS_POR_LOTE {
+            Panics::ExceedTokensToBatchBuy { max: MAX_TOKENS_POR_LOTE as u32 }.panic();
+        }
+        let cantidad = tokens.len() as u64;
+        let gas_resolver = GAS_FOR_RESOLVER_LOTE + GAS_POR_COMPRA_EN_LOTE * cantidad;
+        let gas_promesas = GAS_FOR_BUY_TOKENS * (cantidad + 1);
+        let gas_requerido = gas_promesas + gas_resolver + GAS_FOR_NFT_TRANSFER_PAYOUT * cantidad;
+        let gas_disponible = env::prepaid_gas() - env::used_gas();
+        if gas_disponible < gas_requerido {
+            Panics::NotEnoughGasToBatchBuy { cantidad: cantidad as u32, gas: gas_requerido }
+                .panic();
+        }
+
+        let buyer_id = env::predecessor_account_id();
+        let mut restante = env::attached_deposit();
+        let mut compras = Vec::new();
+        for (contrato_id, token_id) in tokens {
+            let token_key = TokenKey(contrato_id.to_string(), token_id);
+            let token = self.get_token_comprable(&token_key);
+            if buyer_id == token.owner_id {
                 Panics::BuyOwnTokenNotAllowed.panic();
             }
-            let deposit = env::attached_deposit();
-            if deposit < min_precio.0 {
+            let deposit = self.precio_bruto(token.min_precio.0);
+            if deposit > restante {
                 Panics::NotEnoughDepositToBuyToken.panic();
             }
-            self.remove_token_por_id(&token_key, &owner_id, &gate_id, &id_creador);
-            nep171::nft::nft_transfer_payout(
-                buyer_id.try_into().unwrap(),
-                token_id,
-                None,
+            restante -= deposit;
+            self.remove_token_por_id(&token_key, &token.owner_id, &token.gate_id, &token.id_creador);
+            let neto = self.precio_neto(deposit);
+            compras.push((token, U128(deposit), U128(neto)));
+        }
+        if restante > 0 {
+            log!("Refunding unspent {} to `{}`", restante, buyer_id);
+            Promise::new(buyer_id.clone()).transfer(restante);
+        }
+        if compras.is_empty() {
+            return;
+        }
+
+        let gas_disponible = env::prepaid_gas() - env::used_gas();
+        let gas = gas_disponible.saturating_sub(gas_promesas + gas_resolver) / compras.len() as u64;
+        let mut transferencias: Option<Promise> = None;
+        for (token, _, neto) in &compras {
+            let transferencia = nep171::nft::nft_transfer_payout(
+                buyer_id.clone().try_into().unwrap(),
+                token.token_id,
+                Some(token.aprobados_id),
                 None,
-                Some(U128(deposit)),
-                &contrato_id,
+                Some(*neto),
+                &token.contrato_id,
                 0,
-                env::prepaid_gas() / 3,
-            )
-            .then(self_callback::pago(
-                &env::current_account_id(),
-                NO_DEPOSIT,
-                GAS_FOR_ROYALTIES,
-            ));
-        } else {
-            Panics::TokenKeyNotFound { token_key }.panic();
+                gas,
+            );
+            transferencias = Some(match transferencias {
+                None => transferencia,
+                Some(anteriores) => anteriores.and(transferencia),
+            });
+        }
+        transferencias.unwrap().then(self_callback::resolver_compra_por_lote(
+            compras,
+            buyer_id,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            gas_resolver,
+        ));
+    }
+
+    /// Compra el token en venta mas barato de `gate_id` que no supere `max_price`
+    /// ni el deposito adjunto, descontado el fee del market.
+    #[payable]
+    pub fn buy_cheapest(&mut self, gate_id: ValidGateId, max_price: U128) {
+        let buyer_id = env::predecessor_account_id();
+        let tope = max_price.0.min(self.precio_neto(env::attached_deposit()));
+        match self.get_libro(gate_id.as_ref(), 1, tope, |token| token.owner_id != buyer_id).pop() {
+            None => Panics::NoListingUnderPrice { gate_id: gate_id.into(), max_price }.panic(),
+            Some(token) => {
+                let token_key = TokenKey(token.contrato_id.clone(), token.token_id);
+                self.comprar(token_key, token);
+            }
+        }
+    }
+
+    /// Retorna el menor `min_precio` entre los tokens disponibles de `gate_id`
+    pub fn get_precio_piso(&self, gate_id: ValidGateId) -> Option<U128> {
+        self.get_libro(gate_id.as_ref(), 1, Balance::MAX, |_| true).pop().map(|t| t.min_precio)
+    }
+
+    /// Retorna los `limit` tokens disponibles mas baratos de `gate_id`
+    pub fn get_mas_baratos(&self, gate_id: ValidGateId, limit: u32) -> Vec<TokenEnVenta> {
+        self.get_libro(gate_id.as_ref(), limit as usize, Balance::MAX, |_| true)
+    }
+
+    /// Retorna la cantidad de tokens disponibles de `gate_id` por rango de precio.
+    /// Cada rango se identifica por su precio inicial y tiene `ancho` de tamaño.
+    pub fn get_histograma_precios(&self, gate_id: ValidGateId, ancho: U128) -> Vec<(U128, u32)> {
+        let ancho = ancho.0.max(1);
+        let mut histograma: Vec<(U128, u32)> = Vec::new();
+        for token in self.get_libro(gate_id.as_ref(), usize::MAX, Balance::MAX, |_| true) {
+            let desde = token.min_precio.0 / ancho * ancho;
+            match histograma.last_mut() {
+                Some((inicio, cantidad)) if inicio.0 == desde => *cantidad += 1,
+                _ => histograma.push((U128(desde), 1)),
+            }
+        }
+        histograma
+    }
+
+    /// Deposita una oferta por el token `token_id` de `contrato_id`.
+    /// El dueño la acepta aprobando el market con `oferta_id` en el `msg` de `nft_approve`.
+    #[payable]
+    pub fn ofertar_token(&mut self, contrato_id: ValidAccountId, token_id: TokenId) -> OfertaId {
+        let token_key = TokenKey(contrato_id.to_string(), token_id);
+        if let Some(token) = self.tokens_en_venta.get(&token_key) {
+            if token.owner_id == env::predecessor_account_id() {
+                Panics::BuyOwnTokenNotAllowed.panic();
+            }
+        }
+        self.crear_oferta(ObjetivoOferta::Token { contrato_id: contrato_id.into(), token_id })
+    }
+
+    /// Deposita una oferta por cualquier token de `gate_id` en `contrato_id`.
+    #[payable]
+    pub fn ofertar_gate(&mut self, contrato_id: ValidAccountId, gate_id: ValidGateId) -> OfertaId {
+        self.crear_oferta(ObjetivoOferta::Gate {
+            contrato_id: contrato_id.into(),
+            gate_id: gate_id.into(),
+        })
+    }
+
+    /// Retira la oferta `oferta_id` y reembolsa su `monto`.
+    /// Solo puede ejecutarse por quien hizo la oferta.
+    pub fn retirar_oferta(&mut self, oferta_id: OfertaId) {
+        match self.ofertas.get(&oferta_id) {
+            None => Panics::OfertaNotFound { oferta_id }.panic(),
+            Some(oferta) => {
+                if oferta.bidder_id != env::predecessor_account_id() {
+                    Panics::NotOfertaOwner { oferta_id }.panic();
+                }
+                self.remove_oferta(&oferta);
+                Promise::new(oferta.bidder_id).transfer(oferta.monto.0);
+            }
+        }
+    }
+
+    /// Retorna las ofertas por el token `token_id` de `contrato_id`
+    pub fn get_ofertas_por_token(
+        &self,
+        contrato_id: ValidAccountId,
+        token_id: TokenId,
+    ) -> Vec<Oferta> {
+        let token_key = TokenKey(contrato_id.to_string(), token_id);
+        get_ofertas_by(&self.ofertas, &self.ofertas_por_token, &token_key.to_string())
+    }
+
+    /// Retorna las ofertas por cualquier token de `gate_id`
+    pub fn get_ofertas_por_gate(&self, gate_id: ValidGateId) -> Vec<Oferta> {
+        get_ofertas_by(&self.ofertas, &self.ofertas_por_gate, gate_id.as_ref())
+    }
+
+    /// Retorna las ofertas hechas por `bidder_id`
+    pub fn get_ofertas_por_bidder(&self, bidder_id: ValidAccountId) -> Vec<Oferta> {
+        get_ofertas_by(&self.ofertas, &self.ofertas_por_bidder, bidder_id.as_ref())
+    }
+
+    /// Convierte el token en venta en una subasta inglesa hasta `fin_en`.
+    /// El `min_precio` del token es el precio de reserva.
+    /// Solo puede ejecutarse por el dueño del token.
+    pub fn subastar_token(
+        &mut self,
+        contrato_id: ValidAccountId,
+        token_id: TokenId,
+        fin_en: Timestamp,
+        incremento_minimo: U128,
+    ) {
+        let token_key = TokenKey(contrato_id.to_string(), token_id);
+        let token = match self.tokens_en_venta.get(&token_key) {
+            None => Panics::TokenKeyNotFound { token_key }.panic(),
+            Some(token) => token,
+        };
+        if token.owner_id != env::predecessor_account_id() {
+            Panics::NotTokenSeller { token_key }.panic();
+        }
+        if self.subastas.get(&token_key).is_some() {
+            Panics::TokenEnSubasta { token_key }.panic();
+        }
+        let ahora = env::block_timestamp() / 1_000_000;
+        if token.vencido(ahora) {
+            Panics::ListingExpired { token_key }.panic();
+        }
+        if fin_en <= ahora {
+            Panics::SubastaEndInPast { fin_en }.panic();
+        }
+
+        self.subastas.insert(
+            &token_key,
+            &Subasta {
+                contrato_id: token.contrato_id,
+                token_id,
+                owner_id: token.owner_id,
+                precio_reserva: token.min_precio,
+                incremento_minimo,
+                fin_en,
+                mejor_puja: None,
+            },
+        );
+    }
+
+    /// Puja en la subasta del token con el deposito adjunto.
+    /// La puja superada se reembolsa en el momento.
+    /// Una puja cerca del final extiende la subasta `EXTENSION_SUBASTA`.
+    #[payable]
+    pub fn pujar(&mut self, contrato_id: ValidAccountId, token_id: TokenId) {
+        let token_key = TokenKey(contrato_id.to_string(), token_id);
+        let mut subasta = match self.subastas.get(&token_key) {
+            None => Panics::SubastaNotFound { token_key }.panic(),
+            Some(subasta) => subasta,
+        };
+        let ahora = env::block_timestamp() / 1_000_000;
+        if ahora >= subasta.fin_en {
+            Panics::SubastaEnded { token_key }.panic();
+        }
+        let bidder_id = env::predecessor_account_id();
+        if bidder_id == subasta.owner_id {
+            Panics::BuyOwnTokenNotAllowed.panic();
+        }
+        let monto = env::attached_deposit();
+        let minimo = match &subasta.mejor_puja {
+            None => subasta.precio_reserva.0,
+            Some(puja) => puja.monto.0 + subasta.incremento_minimo.0.max(1),
+        };
+        if monto < minimo || self.precio_neto(monto) < subasta.precio_reserva.0 {
+            Panics::PujaTooLow { minimo: U128(minimo) }.panic();
+        }
+
+        if let Some(puja) = subasta.mejor_puja.take() {
+            log!("Refunding {} to outbid `{}`", puja.monto.0, puja.bidder_id);
+            Promise::new(puja.bidder_id).transfer(puja.monto.0);
+        }
+        subasta.mejor_puja = Some(Puja { bidder_id, monto: U128(monto) });
+        if subasta.fin_en - ahora < EXTENSION_SUBASTA {
+            subasta.fin_en = ahora + EXTENSION_SUBASTA;
+            log!("Auction for `{}` extended until {}", token_key, subasta.fin_en);
+        }
+        self.subastas.insert(&token_key, &subasta);
+    }
+
+    /// Liquida la subasta del token una vez terminada.
+    /// Puede ejecutarse por cualquier cuenta.
+    /// Si no hubo pujas, el token sigue en venta a su `min_precio`.
+    pub fn liquidar_subasta(&mut self, contrato_id: ValidAccountId, token_id: TokenId) {
+        let token_key = TokenKey(contrato_id.to_string(), token_id);
+        let subasta = match self.subastas.get(&token_key) {
+            None => Panics::SubastaNotFound { token_key }.panic(),
+            Some(subasta) => subasta,
+        };
+        if env::block_timestamp() / 1_000_000 < subasta.fin_en {
+            Panics::SubastaNotEnded { token_key }.panic();
         }
+        self.subastas.remove(&token_key);
+
+        match subasta.mejor_puja {
+            None => log!("Auction for `{}` ended without bids", token_key),
+            Some(Puja { bidder_id, monto }) => {
+                let token = self.tokens_en_venta.get(&token_key).expect("Token not found");
+                self.remove_token_por_id(
+                    &token_key,
+                    &token.owner_id,
+                    &token.gate_id,
+                    &token.id_creador,
+                );
+                let neto = U128(self.precio_neto(monto.0));
+                nep171::nft::nft_transfer_payout(
+                    bidder_id.clone().try_into().unwrap(),
+                    token_id,
+                    Some(token.aprobados_id),
+                    None,
+                    Some(neto),
+                    &contrato_id,
+                    0,
+                    env::prepaid_gas() / 3,
+                )
+                .then(self_callback::pago(
+                    token,
+                    bidder_id,
+                    monto,
+                    neto,
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_ROYALTIES,
+                ));
+            }
+        }
+    }
+
+    /// Quita de la venta un token vencido.
+    /// Puede ejecutarse por cualquier cuenta.
+    pub fn delist_expired(&mut self, contrato_id: ValidAccountId, token_id: TokenId) {
+        let token_key = TokenKey(contrato_id.to_string(), token_id);
+        let token = match self.tokens_en_venta.get(&token_key) {
+            None => Panics::TokenKeyNotFound { token_key }.panic(),
+            Some(token) => token,
+        };
+        if !token.vencido(env::block_timestamp() / 1_000_000) {
+            Panics::ListingNotExpired { token_key }.panic();
+        }
+        if self.subastas.get(&token_key).is_some() {
+            Panics::TokenEnSubasta { token_key }.panic();
+        }
+        log!("Listing `{}` expired", token_key);
+        self.remove_token_por_id(&token_key, &token.owner_id, &token.gate_id, &token.id_creador);
+    }
+
+    /// Retorna las ultimas ventas del token `token_id` de `contrato_id`,
+    /// de la mas antigua a la mas reciente
+    pub fn get_historial_token(
+        &self,
+        contrato_id: ValidAccountId,
+        token_id: TokenId,
+    ) -> Vec<RegistroVenta> {
+        let token_key = TokenKey(contrato_id.to_string(), token_id);
+        self.ventas_por_token.get(&token_key.to_string()).unwrap_or_default()
+    }
+
+    /// Retorna las ultimas ventas de tokens de `gate_id`,
+    /// de la mas antigua a la mas reciente
+    pub fn get_historial_gate(&self, gate_id: ValidGateId) -> Vec<RegistroVenta> {
+        self.ventas_por_gate.get(gate_id.as_ref()).unwrap_or_default()
+    }
+
+    /// Retorna las estadisticas de ventas de `gate_id`
+    pub fn get_estadisticas_gate(&self, gate_id: ValidGateId) -> EstadisticasVentas {
+        self.estadisticas_por_gate.get(gate_id.as_ref()).unwrap_or_default()
+    }
+
+    /// Retorna la subasta del token, si existe
+    pub fn get_subasta(&self, contrato_id: ValidAccountId, token_id: TokenId) -> Option<Subasta> {
+        self.subastas.get(&TokenKey(contrato_id.to_string(), token_id))
+    }
+
+    /// Retorna todas las subastas
+    pub fn get_subastas(&self) -> Vec<Subasta> {
+        self.subastas.values().collect()
     }
 
     fn remove_token_por_id(
@@ -146,7 +839,9 @@ impl ContratoMercado {
         gate_id: &Option<GateId>,
         id_creador: &Option<AccountId>,
     ) {
-        self.tokens_en_venta.remove(&token_key);
+        if let Some(token) = self.tokens_en_venta.remove(&token_key) {
+            self.remove_libro(token_key, &token);
+        }
         remove_token_por_id_int(&mut self.tokens_por_id, &token_key, &token_key.0, &token_key.1);
         remove_token_por_id_int(&mut self.tokens_por_id_owner, &token_key, &owner_id, token_key);
         if let Some(gate_id) = gate_id {
@@ -165,27 +860,97 @@ impl ContratoMercado {
 
 #[near_ext]
 #[ext_contract(self_callback)]
-trait SelfCallback {
-    fn pago(&mut self);
+pub(crate) trait SelfCallback {
+    fn pago(&mut self, token: TokenEnVenta, buyer_id: AccountId, deposit: U128, neto: U128);
+
+    fn resolver_compra_por_lote(
+        &mut self,
+        compras: Vec<(TokenEnVenta, U128, U128)>,
+        buyer_id: AccountId,
+    );
+
+    fn resolver_oferta(&mut self, oferta: Oferta, token: TokenEnVenta, neto: U128);
+
+    fn relistar(&mut self, token: TokenEnVenta);
 }
 
 #[near_log(skip_args, only_pub)]
 #[near_bindgen]
 impl SelfCallback for ContratoMercado {
+    /// Liquida la compra de `token` una vez resuelto `nft_transfer_payout`.
+    ///
+    /// - Si la transferencia falla, se reembolsa `deposit` a `buyer_id`
+    ///   y el token vuelve a ponerse en venta si su aprobación sigue vigente, ver `relistar`.
+    /// - De lo contrario se distribuye `neto`, el deposito sin el fee del market
+    ///   calculado al momento de la compra, ver `distribuir_pago`.
+    #[private]
+    fn pago(&mut self, token: TokenEnVenta, buyer_id: AccountId, deposit: U128, neto: U128) {
+        self.resolver_pago(env::promise_result(0), token, buyer_id, deposit, neto);
+    }
+
+    /// Liquida cada compra `(token, deposit, neto)` de `buy_tokens` con el resultado
+    /// de su `nft_transfer_payout`, en el mismo orden en que se hicieron las transferencias,
+    /// igual que `pago`.
+    #[private]
+    fn resolver_compra_por_lote(
+        &mut self,
+        compras: Vec<(TokenEnVenta, U128, U128)>,
+        buyer_id: AccountId,
+    ) {
+        for (i, (token, deposit, neto)) in compras.into_iter().enumerate() {
+            let result = env::promise_result(i as u64);
+            self.resolver_pago(result, token, buyer_id.clone(), deposit, neto);
+        }
+    }
+
+    /// Liquida la aceptación de `oferta` por `token`.
+    /// Si la transferencia falla, la oferta se restablece y su monto sigue depositado.
     #[private]
-    fn pago(&mut self) {
+    fn resolver_oferta(&mut self, oferta: Oferta, token: TokenEnVenta, neto: U128) {
         match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
-            PromiseResult::Failed => unreachable!(),
+            PromiseResult::Failed => {
+                let token_key = TokenKey(token.contrato_id.clone(), token.token_id);
+                log!(
+                    "Transfer of `{}` failed, offer {:?} is active again",
+                    token_key,
+                    oferta.oferta_id
+                );
+                self.insert_oferta(&oferta);
+            }
             PromiseResult::Successful(value) => {
-                if let Ok(payout) = serde_json::from_slice::<Payout>(&value) {
-                    for (receiver_id, amount) in payout {
-                        Promise::new(receiver_id).transfer(amount.0);
+                self.distribuir_pago(&value, &token, &oferta.bidder_id, oferta.monto, neto)
+            }
+        }
+    }
+
+    /// Vuelve a poner en venta `token` luego de una transferencia fallida,
+    /// solo si `nft_token` muestra que su dueño, su aprobación y su `min_precio` no cambiaron.
+    /// De lo contrario el listado se descarta.
+    #[private]
+    fn relistar(&mut self, token: TokenEnVenta) {
+        let token_key = TokenKey(token.contrato_id.clone(), token.token_id);
+        let vigente = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                match serde_json::from_slice::<Option<Token>>(&value) {
+                    Ok(Some(actual)) => {
+                        let approval = actual.approvals.get(&env::current_account_id());
+                        actual.owner_id == token.owner_id
+                            && approval.map_or(false, |a| {
+                                a.aprobados_id == token.aprobados_id
+                                    && a.min_precio == token.min_precio
+                            })
                     }
-                } else {
-                    unreachable!();
+                    _ => false,
                 }
             }
+            _ => false,
+        };
+        if !vigente {
+            log!("Approval of `{}` changed, it is no longer on sale", token_key);
+        } else if self.tokens_en_venta.get(&token_key).is_none() {
+            log!("Token `{}` is on sale again", token_key);
+            self.insert_token_en_venta(&token);
         }
     }
 }
@@ -204,8 +969,26 @@ impl NonFungibleTokenApprovalsReceiver for ContratoMercado {
         match serde_json::from_str::<MarketApproveMsg>(&msg) {
             Ok(approve_msg) => {
                 let contrato_id = env::predecessor_account_id();
+                self.assert_contrato_nft(&contrato_id);
                 let owner_id = owner_id.to_string();
-                self.add_token(&owner_id, &contrato_id, token_id, approve_msg, aprobados_id);
+                if let Some(expira_en) = approve_msg.expira_en {
+                    if expira_en <= env::block_timestamp() / 1_000_000 {
+                        Panics::ExpiryInPast { expira_en }.panic();
+                    }
+                }
+                match approve_msg.oferta_id {
+                    None => {
+                        self.add_token(&owner_id, &contrato_id, token_id, approve_msg, aprobados_id)
+                    }
+                    Some(oferta_id) => self.aceptar_oferta(
+                        &owner_id,
+                        &contrato_id,
+                        token_id,
+                        approve_msg,
+                        aprobados_id,
+                        oferta_id,
+                    ),
+                }
             }
             Err(err) => {
                 let reason = err.to_string();
@@ -214,27 +997,55 @@ impl NonFungibleTokenApprovalsReceiver for ContratoMercado {
         }
     }
 
+    fn nft_on_price_update(
+        &mut self,
+        token_id: TokenId,
+        owner_id: ValidAccountId,
+        aprobados_id: U64,
+        min_precio: U128,
+    ) {
+        let contrato_id = env::predecessor_account_id();
+        self.assert_contrato_nft(&contrato_id);
+        let token_key = TokenKey(contrato_id, token_id);
+        self.update_price(token_key, owner_id.as_ref(), aprobados_id, min_precio);
+    }
+
     fn nft_on_revoke(&mut self, token_id: TokenId) {
         let contrato_id = env::predecessor_account_id();
         let token_key = TokenKey(contrato_id, token_id);
 
         if let Some(token) = self.tokens_en_venta.get(&token_key) {
-            assert_eq!(token.contrato_id, token_key.0);
-            self.remove_token_por_id(&token_key, &token.owner_id, &token.gate_id, &token.id_creador);
+            self.revocar_token(&token_key, token);
         } else {
             Panics::TokenKeyNotFound { token_key }.panic();
         }
     }
 
+    fn batch_on_revoke(&mut self, token_ids: Vec<TokenId>) {
+        let contrato_id = env::predecessor_account_id();
+        for token_id in token_ids {
+            let token_key = TokenKey(contrato_id.clone(), token_id);
+            match self.tokens_en_venta.get(&token_key) {
+                None => log!("Token `{}` is not on sale", token_key),
+                Some(token) => self.revocar_token(&token_key, token),
+            }
+        }
+    }
+
     fn batch_on_approve(
         &mut self,
         tokens: Vec<(TokenId, MarketApproveMsg)>,
         owner_id: ValidAccountId,
     ) {
         let contrato_id = env::predecessor_account_id();
+        self.assert_contrato_nft(&contrato_id);
         let owner_id = owner_id.to_string();
         for (token_id, approve_msg) in tokens {
-            self.add_token(&owner_id, &contrato_id, token_id, approve_msg, U64(0));
+            let aprobados_id = match approve_msg.aprobados_id {
+                None => Panics::MsgFormatApprovalIdMissing { token_id }.panic(),
+                Some(aprobados_id) => aprobados_id,
+            };
+            self.add_token(&owner_id, &contrato_id, token_id, approve_msg, aprobados_id);
         }
     }
 }
@@ -249,44 +1060,500 @@ impl ContratoMercado {
         approve_msg: MarketApproveMsg,
         aprobados_id: U64,
     ) {
+        self.insert_token_en_venta(&TokenEnVenta {
+            contrato_id: contrato_id.clone(),
+            token_id,
+            owner_id: owner_id.clone().into(),
+            aprobados_id,
+            min_precio: approve_msg.min_precio,
+            gate_id: approve_msg.gate_id.map(|g| g.to_string()),
+            id_creador: approve_msg.id_creador,
+            expira_en: approve_msg.expira_en,
+        });
+    }
+
+    /// Cambia el `min_precio` del token en venta
+    /// una vez que `owner_id` lo actualizó en la aprobación del contrato NFT,
+    /// siempre que el listado sea de esa misma aprobación.
+    fn update_price(
+        &mut self,
+        token_key: TokenKey,
+        owner_id: &AccountId,
+        aprobados_id: U64,
+        min_precio: U128,
+    ) {
+        let mut token = match self.tokens_en_venta.get(&token_key) {
+            None => Panics::TokenKeyNotFound { token_key }.panic(),
+            Some(token) => token,
+        };
+        if &token.owner_id != owner_id || token.aprobados_id != aprobados_id {
+            Panics::NotTokenSeller { token_key }.panic();
+        }
+        if self.subastas.get(&token_key).is_some() {
+            Panics::TokenEnSubasta { token_key }.panic();
+        }
+
+        log!("Price of `{}` updated to {}", token_key, min_precio.0);
+        self.remove_libro(&token_key, &token);
+        token.min_precio = min_precio;
+        self.tokens_en_venta.insert(&token_key, &token);
+        self.insert_libro(&token_key, &token);
+    }
+
+    /// Acepta la oferta `oferta_id` por el token recién aprobado por `owner_id`,
+    /// transfiriendolo a quien hizo la oferta.
+    fn aceptar_oferta(
+        &mut self,
+        owner_id: &AccountId,
+        contrato_id: &String,
+        token_id: TokenId,
+        approve_msg: MarketApproveMsg,
+        aprobados_id: U64,
+        oferta_id: OfertaId,
+    ) {
+        let oferta = match self.ofertas.get(&oferta_id) {
+            None => Panics::OfertaNotFound { oferta_id }.panic(),
+            Some(oferta) => oferta,
+        };
+        let aplica = match &oferta.objetivo {
+            ObjetivoOferta::Token { contrato_id: c, token_id: t } => {
+                c == contrato_id && t == &token_id
+            }
+            ObjetivoOferta::Gate { contrato_id: c, gate_id } => {
+                c == contrato_id
+                    && approve_msg.gate_id.as_ref().map_or(false, |g| g.as_ref() == gate_id)
+            }
+        };
         let token_key = TokenKey(contrato_id.clone(), token_id);
-        self.tokens_en_venta.insert(
-            &token_key,
-            &TokenEnVenta {
-                contrato_id: contrato_id.clone(),
-                token_id,
-                owner_id: owner_id.clone().into(),
-                aprobados_id,
-                min_precio: approve_msg.min_precio,
-                gate_id: approve_msg.gate_id.clone().map(|g| g.to_string()),
-                id_creador: approve_msg.id_creador.clone(),
-            },
+        if !aplica {
+            Panics::OfertaNotForToken { oferta_id, token_key }.panic();
+        }
+        if &oferta.bidder_id == owner_id {
+            Panics::BuyOwnTokenNotAllowed.panic();
+        }
+        if self.precio_neto(oferta.monto.0) < approve_msg.min_precio.0 {
+            Panics::NotEnoughDepositToBuyToken.panic();
+        }
+
+        self.remove_oferta(&oferta);
+        let token = TokenEnVenta {
+            contrato_id: contrato_id.clone(),
+            token_id,
+            owner_id: owner_id.clone(),
+            aprobados_id,
+            min_precio: approve_msg.min_precio,
+            gate_id: approve_msg.gate_id.map(|g| g.to_string()),
+            id_creador: approve_msg.id_creador,
+            expira_en: None,
+        };
+        let neto = U128(self.precio_neto(oferta.monto.0));
+        nep171::nft::nft_transfer_payout(
+            oferta.bidder_id.clone().try_into().unwrap(),
+            token_id,
+            Some(aprobados_id),
+            None,
+            Some(neto),
+            contrato_id,
+            0,
+            env::prepaid_gas() / 3,
+        )
+        .then(self_callback::resolver_oferta(
+            oferta,
+            token,
+            neto,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            env::prepaid_gas() / 3,
+        ));
+    }
+
+    /// Quita `token` de la venta y cancela su subasta, reembolsando la mejor puja.
+    fn revocar_token(&mut self, token_key: &TokenKey, token: TokenEnVenta) {
+        assert_eq!(token.contrato_id, token_key.0);
+        self.remove_token_por_id(token_key, &token.owner_id, &token.gate_id, &token.id_creador);
+        if let Some(Subasta { mejor_puja: Some(puja), .. }) = self.subastas.remove(token_key) {
+            log!("Auction for `{}` cancelled, refunding `{}`", token_key, puja.bidder_id);
+            Promise::new(puja.bidder_id).transfer(puja.monto.0);
+        }
+    }
+
+    /// Retorna lo que queda de `monto` luego de descontar el fee del market.
+    fn precio_neto(&self, monto: Balance) -> Balance {
+        monto - self.fee_mercado.mult(monto)
+    }
+
+    /// Retorna el menor monto que, luego de descontar el fee del market, cubre `precio`,
+    /// es decir, el menor deposito que acepta `buy_token` por un token de ese `min_precio`.
+    fn precio_bruto(&self, precio: Balance) -> Balance {
+        let Fraction { num, den } = self.fee_mercado;
+        if num == den {
+            Panics::NotEnoughDepositToBuyToken.panic();
+        }
+        let (neto, den) = ((den - num) as Balance, den as Balance);
+        // `precio_neto(monto)` redondea el fee hacia abajo, por lo que cubre `precio`
+        // desde `(precio - 1) * den / neto + 1`.
+        match precio.checked_sub(1) {
+            None => 0,
+            Some(precio) => precio / neto * den + precio % neto * den / neto + 1,
+        }
+    }
+
+    /// Retorna el token en venta `token_key` si se puede comprar,
+    /// es decir, si no esta en subasta ni vencido.
+    fn get_token_comprable(&self, token_key: &TokenKey) -> TokenEnVenta {
+        if self.subastas.get(token_key).is_some() {
+            Panics::TokenEnSubasta { token_key: token_key.clone() }.panic();
+        }
+        match self.tokens_en_venta.get(token_key) {
+            None => Panics::TokenKeyNotFound { token_key: token_key.clone() }.panic(),
+            Some(token) => {
+                if token.vencido(env::block_timestamp() / 1_000_000) {
+                    Panics::ListingExpired { token_key: token_key.clone() }.panic();
+                }
+                token
+            }
+        }
+    }
+
+    /// Liquida la compra de `token` segun `result`, ver `pago`.
+    fn resolver_pago(
+        &mut self,
+        result: PromiseResult,
+        token: TokenEnVenta,
+        buyer_id: AccountId,
+        deposit: U128,
+        neto: U128,
+    ) {
+        let token_key = TokenKey(token.contrato_id.clone(), token.token_id);
+        match result {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Failed => {
+                log!(
+                    "Transfer of `{}` failed, refunding {} to `{}`",
+                    token_key,
+                    deposit.0,
+                    buyer_id
+                );
+                Promise::new(buyer_id).transfer(deposit.0);
+                let contrato_id = token.contrato_id.clone();
+                nep171::nft::nft_token(token.token_id, &contrato_id, NO_DEPOSIT, GAS_FOR_NFT_TOKEN)
+                    .then(self_callback::relistar(
+                        token,
+                        &env::current_account_id(),
+                        NO_DEPOSIT,
+                        GAS_FOR_RELISTAR,
+                    ));
+            }
+            PromiseResult::Successful(value) => {
+                self.distribuir_pago(&value, &token, &buyer_id, deposit, neto)
+            }
+        }
+    }
+
+    fn assert_owner(&self) {
+        if env::predecessor_account_id() != self.owner_id {
+            Panics::OwnerRestrictedOperation.panic();
+        }
+    }
+
+    fn assert_contrato_nft(&self, contrato_id: &AccountId) {
+        if !self.contratos_nft.contains(contrato_id) {
+            Panics::ContratoNftNotAllowed { contrato_id: contrato_id.clone() }.panic();
+        }
+    }
+
+    /// Registra la venta de `token` a `comprador_id`, acumula el fee del market,
+    /// la diferencia entre `deposit` y `neto`, y distribuye `neto`
+    /// segun el `Payout` retornado por `nft_transfer_payout`.
+    /// `neto` se calcula al momento de la compra, de modo que un cambio de `fee_mercado`
+    /// mientras se resuelve la transferencia no altera lo que se paga.
+    /// Si el `Payout` no se puede leer o supera `neto`,
+    /// el token ya fue transferido, por lo que `neto` se paga completo al vendedor.
+    fn distribuir_pago(
+        &mut self,
+        value: &[u8],
+        token: &TokenEnVenta,
+        comprador_id: &AccountId,
+        deposit: U128,
+        neto: U128,
+    ) {
+        let token_key = TokenKey(token.contrato_id.clone(), token.token_id);
+        self.registrar_venta(&token_key, token, comprador_id, deposit);
+        let neto = neto.0;
+        log!("Market fee of {} for `{}`", deposit.0 - neto, token_key);
+        self.fees_acumulados += deposit.0 - neto;
+
+        match serde_json::from_slice::<Option<Payout>>(value) {
+            Ok(Some(payout)) if payout_total(&payout).map_or(false, |t| t <= neto) => {
+                let mut restante = neto;
+                for (receiver_id, amount) in payout {
+                    if amount.0 > 0 {
+                        log!("Paying {} to `{}` for `{}`", amount.0, receiver_id, token_key);
+                        restante -= amount.0;
+                        Promise::new(receiver_id).transfer(amount.0);
+                    }
+                }
+                if restante > 0 {
+                    log!("Paying remaining {} to seller `{}`", restante, token.owner_id);
+                    Promise::new(token.owner_id.clone()).transfer(restante);
+                }
+            }
+            _ => {
+                log!(
+                    "Invalid payout for `{}`, paying {} to seller `{}`",
+                    token_key,
+                    neto,
+                    token.owner_id
+                );
+                Promise::new(token.owner_id.clone()).transfer(neto);
+            }
+        }
+    }
+
+    /// Agrega la venta de `token` al historial y a las estadisticas de su `gate_id`.
+    fn registrar_venta(
+        &mut self,
+        token_key: &TokenKey,
+        token: &TokenEnVenta,
+        comprador_id: &AccountId,
+        precio: U128,
+    ) {
+        let venta = RegistroVenta {
+            contrato_id: token.contrato_id.clone(),
+            token_id: token.token_id,
+            comprador_id: comprador_id.clone(),
+            vendedor_id: token.owner_id.clone(),
+            precio,
+            vendido_en: env::block_timestamp() / 1_000_000,
+        };
+        agregar_venta(&mut self.ventas_por_token, &token_key.to_string(), venta.clone());
+        if let Some(gate_id) = &token.gate_id {
+            agregar_venta(&mut self.ventas_por_gate, gate_id, venta);
+
+            let mut estadisticas = self.estadisticas_por_gate.get(gate_id).unwrap_or_default();
+            estadisticas.cantidad += 1;
+            estadisticas.volumen = U128(estadisticas.volumen.0 + precio.0);
+            estadisticas.ultimo_precio = precio;
+            estadisticas.precio_promedio =
+                U128(estadisticas.volumen.0 / estadisticas.cantidad as Balance);
+            self.estadisticas_por_gate.insert(gate_id, &estadisticas);
+        }
+    }
+
+    /// Crea una oferta sobre `objetivo` con el deposito adjunto.
+    fn crear_oferta(&mut self, objetivo: ObjetivoOferta) -> OfertaId {
+        let monto = env::attached_deposit();
+        if monto == 0 {
+            Panics::ZeroDepositNotAllowed.panic();
+        }
+        self.ultima_oferta_id += 1;
+        let oferta = Oferta {
+            oferta_id: U64(self.ultima_oferta_id),
+            bidder_id: env::predecessor_account_id(),
+            objetivo,
+            monto: U128(monto),
+            creado_en: env::block_timestamp() / 1_000_000,
+        };
+        self.insert_oferta(&oferta);
+        oferta.oferta_id
+    }
+
+    /// Guarda `oferta` y la agrega a los indices.
+    fn insert_oferta(&mut self, oferta: &Oferta) {
+        self.ofertas.insert(&oferta.oferta_id, oferta);
+        match &oferta.objetivo {
+            ObjetivoOferta::Token { contrato_id, token_id } => insert_token_id_to(
+                &mut self.ofertas_por_token,
+                &TokenKey(contrato_id.clone(), *token_id).to_string(),
+                &oferta.oferta_id,
+                Keys::OfertasPorTokenValor,
+            ),
+            ObjetivoOferta::Gate { gate_id, .. } => insert_token_id_to(
+                &mut self.ofertas_por_gate,
+                gate_id,
+                &oferta.oferta_id,
+                Keys::OfertasPorGateValor,
+            ),
+        }
+        insert_token_id_to(
+            &mut self.ofertas_por_bidder,
+            &oferta.bidder_id,
+            &oferta.oferta_id,
+            Keys::OfertasPorBidderValor,
         );
+    }
+
+    /// Elimina `oferta` y la quita de los indices.
+    fn remove_oferta(&mut self, oferta: &Oferta) {
+        self.ofertas.remove(&oferta.oferta_id);
+        match &oferta.objetivo {
+            ObjetivoOferta::Token { contrato_id, token_id } => remove_oferta_de(
+                &mut self.ofertas_por_token,
+                &TokenKey(contrato_id.clone(), *token_id).to_string(),
+                &oferta.oferta_id,
+            ),
+            ObjetivoOferta::Gate { gate_id, .. } => {
+                remove_oferta_de(&mut self.ofertas_por_gate, gate_id, &oferta.oferta_id)
+            }
+        }
+        remove_oferta_de(&mut self.ofertas_por_bidder, &oferta.bidder_id, &oferta.oferta_id);
+    }
+
+    /// Compra `token` con el deposito adjunto.
+    fn comprar(&mut self, token_key: TokenKey, token: TokenEnVenta) {
+        let buyer_id = env::predecessor_account_id();
+        if buyer_id == token.owner_id {
+            Panics::BuyOwnTokenNotAllowed.panic();
+        }
+        let deposit = env::attached_deposit();
+        let neto = self.precio_neto(deposit);
+        if neto < token.min_precio.0 {
+            Panics::NotEnoughDepositToBuyToken.panic();
+        }
+        self.remove_token_por_id(&token_key, &token.owner_id, &token.gate_id, &token.id_creador);
+        nep171::nft::nft_transfer_payout(
+            buyer_id.clone().try_into().unwrap(),
+            token.token_id,
+            Some(token.aprobados_id),
+            None,
+            Some(U128(neto)),
+            &token.contrato_id,
+            0,
+            env::prepaid_gas() / 3,
+        )
+        .then(self_callback::pago(
+            token,
+            buyer_id,
+            U128(deposit),
+            U128(neto),
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_ROYALTIES,
+        ));
+    }
+
+    /// Retorna hasta `limit` tokens de `gate_id` ordenados por `min_precio`,
+    /// sin superar `max_precio` y que cumplan `f`.
+    /// Se omiten los tokens en subasta y los vencidos.
+    /// Tambien se omiten las entradas del libro que ya no corresponden a un token en venta.
+    fn get_libro<F: Fn(&TokenEnVenta) -> bool>(
+        &self,
+        gate_id: &GateId,
+        limit: usize,
+        max_precio: Balance,
+        f: F,
+    ) -> Vec<TokenEnVenta> {
+        let mut result = Vec::new();
+        if let Some(libro) = self.libro_por_gate.get(gate_id) {
+            let ahora = env::block_timestamp() / 1_000_000;
+            for ((precio, token_key), _) in libro.iter() {
+                if result.len() >= limit || precio > max_precio {
+                    break;
+                }
+                if self.subastas.get(&token_key).is_some() {
+                    continue;
+                }
+                let token = match self.tokens_en_venta.get(&token_key) {
+                    Some(token) if token.min_precio.0 == precio => token,
+                    _ => continue,
+                };
+                if !token.vencido(ahora) && f(&token) {
+                    result.push(token);
+                }
+            }
+        }
+        result
+    }
+
+    /// Pagina los tokens de `libro` en el `orden` de `filtro`.
+    /// Los tokens se leen de a uno desde el extremo del libro y la lectura termina
+    /// al completar la pagina o al pasar el rango de precios de `filtro`.
+    fn paginar_libro(
+        &self,
+        libro: &TreeMap<(Balance, TokenKey), ()>,
+        from_index: Option<U64>,
+        limit: Option<u32>,
+        filtro: &FiltroVenta,
+    ) -> Vec<TokenEnVenta> {
+        let claves: Box<dyn Iterator<Item = _>> = match filtro.orden {
+            Some(Orden::Desc) => Box::new(libro.iter_rev()),
+            _ => Box::new(libro.iter()),
+        };
+        let min = filtro.precio_min.map_or(0, |min| min.0);
+        let max = filtro.precio_max.map_or(Balance::MAX, |max| max.0);
+        let en_rango = |(precio, _): &(Balance, TokenKey)| min <= *precio && *precio <= max;
+        let tokens = claves
+            .map(|(clave, _)| clave)
+            .skip_while(|clave| !en_rango(clave))
+            .take_while(en_rango)
+            .filter_map(|(precio, token_key)| {
+                self.tokens_en_venta.get(&token_key).filter(|token| token.min_precio.0 == precio)
+            });
+        let filtro = FiltroVenta { orden: None, ..filtro.clone() };
+        paginar(tokens, from_index, limit, Some(filtro))
+    }
+
+    /// Agrega `token` al libro de precios de su `gate_id`.
+    fn insert_libro(&mut self, token_key: &TokenKey, token: &TokenEnVenta) {
+        if let Some(gate_id) = &token.gate_id {
+            let mut libro = self
+                .libro_por_gate
+                .get(gate_id)
+                .unwrap_or_else(|| TreeMap::new(Keys::LibroPorGateValor(crypto_hash(gate_id))));
+            libro.insert(&(token.min_precio.0, token_key.clone()), &());
+            self.libro_por_gate.insert(gate_id, &libro);
+        }
+    }
+
+    /// Quita `token` del libro de precios de su `gate_id`.
+    fn remove_libro(&mut self, token_key: &TokenKey, token: &TokenEnVenta) {
+        if let Some(gate_id) = &token.gate_id {
+            if let Some(mut libro) = self.libro_por_gate.get(gate_id) {
+                libro.remove(&(token.min_precio.0, token_key.clone()));
+                self.libro_por_gate.insert(gate_id, &libro);
+            }
+        }
+    }
+
+    /// Pone `token` en venta y lo agrega a los indices.
+    /// Si el token ya estaba en venta, primero se quita el listado anterior de los indices.
+    fn insert_token_en_venta(&mut self, token: &TokenEnVenta) {
+        let token_key = TokenKey(token.contrato_id.clone(), token.token_id);
+        if let Some(anterior) = self.tokens_en_venta.get(&token_key) {
+            self.remove_token_por_id(
+                &token_key,
+                &anterior.owner_id,
+                &anterior.gate_id,
+                &anterior.id_creador,
+            );
+        }
+        self.tokens_en_venta.insert(&token_key, token);
+        self.insert_libro(&token_key, token);
 
         insert_token_id_to(
             &mut self.tokens_por_id,
-            &contrato_id,
-            &token_id,
+            &token.contrato_id,
+            &token.token_id,
             Keys::TokensPorIdValor,
         );
         insert_token_id_to(
             &mut self.tokens_por_id_owner,
-            &owner_id.into(),
+            &token.owner_id,
             &token_key,
             Keys::TokensPorIdOwnerValor,
         );
-        if let Some(gate_id) = approve_msg.gate_id {
+        if let Some(gate_id) = &token.gate_id {
             insert_token_id_to(
                 &mut self.tokens_por_id_gate,
-                gate_id.as_ref(),
+                gate_id,
                 &token_key,
                 Keys::TokensPorIdGateValor,
             );
         }
-        if let Some(id_creador) = approve_msg.id_creador {
+        if let Some(id_creador) = &token.id_creador {
             insert_token_id_to(
                 &mut self.tokens_por_id_creador,
-                &id_creador,
+                id_creador,
                 &token_key,
                 Keys::TokensPorIdCreadorValor,
             );
@@ -305,19 +1572,96 @@ fn insert_token_id_to<T: BorshSerialize + BorshDeserialize, F: FnOnce(CryptoHash
     tokens_map.insert(key, &tids);
 }
 
+/// Agrega `venta` al historial de `key`, descartando la venta mas antigua
+/// si se supera `MAX_HISTORIAL_VENTAS`.
+fn agregar_venta(
+    ventas_map: &mut LookupMap<String, Vec<RegistroVenta>>,
+    key: &String,
+    venta: RegistroVenta,
+) {
+    let mut ventas = ventas_map.get(key).unwrap_or_default();
+    if ventas.len() >= MAX_HISTORIAL_VENTAS {
+        ventas.remove(0);
+    }
+    ventas.push(venta);
+    ventas_map.insert(key, &ventas);
+}
+
+/// Suma los montos de `payout`, o `None` si la suma desborda.
+fn payout_total(payout: &Payout) -> Option<Balance> {
+    payout.values().try_fold(0u128, |total, amount| total.checked_add(amount.0))
+}
+
 fn get_tokens_by<K: BorshSerialize>(
     ts: &UnorderedMap<TokenKey, TokenEnVenta>,
     tokens_map: &LookupMap<K, UnorderedSet<TokenKey>>,
     key: &K,
+    from_index: Option<U64>,
+    limit: Option<u32>,
+    filtro: Option<FiltroVenta>,
 ) -> Vec<TokenEnVenta> {
     match tokens_map.get(&key) {
         None => Vec::new(),
-        Some(tids) => {
-            tids.iter().map(|token_id| ts.get(&token_id).expect("Token not found")).collect()
+        Some(tids) => paginar(
+            tids.iter().map(|token_id| ts.get(&token_id).expect("Token not found")),
+            from_index,
+            limit,
+            filtro,
+        ),
+    }
+}
+
+/// Aplica `filtro` a `tokens` y retorna hasta `limit` tokens desde `from_index`.
+/// `limit` no puede superar `MAX_TOKENS_POR_CONSULTA`.
+/// Sin `orden` los tokens se leen de a uno hasta completar la pagina,
+/// con `orden` se leen todos los tokens para poder ordenarlos.
+fn paginar<I: Iterator<Item = TokenEnVenta>>(
+    tokens: I,
+    from_index: Option<U64>,
+    limit: Option<u32>,
+    filtro: Option<FiltroVenta>,
+) -> Vec<TokenEnVenta> {
+    let filtro = filtro.unwrap_or_default();
+    let from_index = from_index.map_or(0, |i| i.0 as usize);
+    let limit = limit.map_or(MAX_TOKENS_POR_CONSULTA, |limit| limit.min(MAX_TOKENS_POR_CONSULTA));
+    let limit = limit as usize;
+
+    let tokens = tokens.filter(|token| filtro.incluye(token));
+    match filtro.orden {
+        None => tokens.skip(from_index).take(limit).collect(),
+        Some(orden) => {
+            let mut tokens = tokens.collect::<Vec<_>>();
+            tokens.sort_by_key(|token| token.min_precio.0);
+            if let Orden::Desc = orden {
+                tokens.reverse();
+            }
+            tokens.into_iter().skip(from_index).take(limit).collect()
         }
     }
 }
 
+fn get_ofertas_by(
+    ofertas: &UnorderedMap<OfertaId, Oferta>,
+    ofertas_map: &LookupMap<String, UnorderedSet<OfertaId>>,
+    key: &String,
+) -> Vec<Oferta> {
+    match ofertas_map.get(&key) {
+        None => Vec::new(),
+        Some(ids) => ids.iter().map(|id| ofertas.get(&id).expect("Offer not found")).collect(),
+    }
+}
+
+fn remove_oferta_de(
+    ofertas_map: &mut LookupMap<String, UnorderedSet<OfertaId>>,
+    key: &String,
+    oferta_id: &OfertaId,
+) {
+    if let Some(mut ids) = ofertas_map.get(&key) {
+        ids.remove(oferta_id);
+        ofertas_map.insert(&key, &ids);
+    }
+}
+
 fn remove_token_por_id_int<T: BorshSerialize + BorshDeserialize + Clone, K: BorshSerialize>(
     tokens_map: &mut LookupMap<K, UnorderedSet<T>>,
     t: &TokenKey,
@@ -343,13 +1687,73 @@ pub enum Panics {
     /// Thrown when `nft_on_approve` does not find `min_precio`.
     #[panic_msg = "Could not find min_precio in msg: {}"]
     MsgFormatMinPriceMissing { reason: String },
+    /// Thrown when `batch_on_approve` does not find `aprobados_id`.
+    #[panic_msg = "Could not find aprobados_id in msg of token `{:?}`"]
+    MsgFormatApprovalIdMissing { token_id: TokenId },
     /// Thrown when the `token_key` was not found.
     #[panic_msg = "Token Key `{}` was not found"]
     TokenKeyNotFound { token_key: TokenKey },
+    /// Thrown when a method restricted to the market owner is called by another account.
+    #[panic_msg = "Operation is allowed only for the market owner"]
+    OwnerRestrictedOperation,
+    /// Thrown when an NFT contract not supported by the market lists a token.
+    #[panic_msg = "NFT contract `{}` is not supported by this market"]
+    ContratoNftNotAllowed { contrato_id: AccountId },
     /// Thrown when buyer attempts to buy own token.
     #[panic_msg = "Buyer cannot buy own token"]
     BuyOwnTokenNotAllowed,
     /// Thrown when deposit is not enough to buy a token.
     #[panic_msg = "Not enough deposit to cover token minimum price"]
     NotEnoughDepositToBuyToken,
+    /// Thrown when an offer is made without attached deposit.
+    #[panic_msg = "An offer must have a positive deposit"]
+    ZeroDepositNotAllowed,
+    /// Thrown when the `oferta_id` was not found.
+    #[panic_msg = "Offer `{:?}` was not found"]
+    OfertaNotFound { oferta_id: OfertaId },
+    /// Thrown when someone other than the bidder withdraws an offer.
+    #[panic_msg = "Offer `{:?}` can only be withdrawn by its bidder"]
+    NotOfertaOwner { oferta_id: OfertaId },
+    /// Thrown when an accepted offer does not target the approved token.
+    #[panic_msg = "Offer `{:?}` does not apply to token `{}`"]
+    OfertaNotForToken { oferta_id: OfertaId, token_key: TokenKey },
+    /// Thrown when someone other than the seller operates on a token on sale.
+    #[panic_msg = "Only the seller can modify token `{}`"]
+    NotTokenSeller { token_key: TokenKey },
+    /// Thrown when a token on auction is bought or auctioned again.
+    #[panic_msg = "Token `{}` is on auction"]
+    TokenEnSubasta { token_key: TokenKey },
+    /// Thrown when an auction is created with an end time in the past.
+    #[panic_msg = "Auction end `{}` must be in the future"]
+    SubastaEndInPast { fin_en: Timestamp },
+    /// Thrown when there is no auction for the token.
+    #[panic_msg = "Token `{}` is not on auction"]
+    SubastaNotFound { token_key: TokenKey },
+    /// Thrown when bidding on an auction that already ended.
+    #[panic_msg = "Auction for token `{}` has ended"]
+    SubastaEnded { token_key: TokenKey },
+    /// Thrown when settling an auction before its end time.
+    #[panic_msg = "Auction for token `{}` has not ended yet"]
+    SubastaNotEnded { token_key: TokenKey },
+    /// Thrown when a listing is created with an expiry in the past.
+    #[panic_msg = "Listing expiry `{}` must be in the future"]
+    ExpiryInPast { expira_en: Timestamp },
+    /// Thrown when buying or auctioning an expired listing.
+    #[panic_msg = "Listing of token `{}` has expired"]
+    ListingExpired { token_key: TokenKey },
+    /// Thrown when delisting a listing that has not expired.
+    #[panic_msg = "Listing of token `{}` has not expired"]
+    ListingNotExpired { token_key: TokenKey },
+    /// Thrown when `buy_cheapest` finds no listing within the price.
+    #[panic_msg = "No token of gate ID `{}` is on sale for at most `{:?}`"]
+    NoListingUnderPrice { gate_id: GateId, max_price: U128 },
+    /// Thrown when `buy_tokens` receives too many tokens.
+    #[panic_msg = "At most {} tokens can be bought in a batch"]
+    ExceedTokensToBatchBuy { max: u32 },
+    /// Thrown when `buy_tokens` is not given enough gas to settle every token.
+    #[panic_msg = "Buying {} tokens in a batch requires at least {} gas"]
+    NotEnoughGasToBatchBuy { cantidad: u32, gas: Gas },
+    /// Thrown when a bid does not reach the reserve price or the minimum increment.
+    #[panic_msg = "Bid must be at least `{:?}`"]
+    PujaTooLow { minimo: U128 },
 }
\ No newline at end of file
@@ -0,0 +1,221 @@
+//! Migraciones del estado de `ContratoNft` entre versiones del contrato.
+//!
+//! `iniciar_migracion` lee el estado con el formato de la version desplegada,
+//! y `migrar_estado` lleva sus tickets y tokens al formato actual por partes.
+//! Deben ejecutarse por la cuenta del contrato o por el admin luego de deployar.
+use crate::{
+    crypto_hash, fraction::Fraction, nep177::NFTContractMetadata, Collectible, ContratoNft, GateId,
+    Keys, Metadata, MetadataToken, Panic, Timestamp, Token, TokenApproval, TokenId,
+};
+use near_sdk::{
+    borsh::{self, BorshDeserialize, BorshSerialize},
+    collections::{LookupMap, UnorderedMap, UnorderedSet},
+    env,
+    json_types::U64,
+    AccountId,
+};
+use std::collections::HashMap;
+
+/// `Metadata` desplegada, con `copias` de tipo `u16`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct MetadataBase {
+    pub(crate) titulo: Option<String>,
+    pub(crate) descripcion: Option<String>,
+    pub(crate) media: Option<String>,
+    pub(crate) media_hash: Option<String>,
+    pub(crate) copias: Option<u16>,
+    pub(crate) emitido_en: Option<Timestamp>,
+    pub(crate) expira_en: Option<Timestamp>,
+    pub(crate) comienzo_en: Option<Timestamp>,
+    pub(crate) actualizado_en: Option<Timestamp>,
+    pub(crate) extra: Option<String>,
+    pub(crate) referencia: Option<String>,
+    pub(crate) referencia_hash: Option<String>,
+}
+
+impl From<MetadataBase> for Metadata {
+    fn from(metadata: MetadataBase) -> Self {
+        Self {
+            titulo: metadata.titulo,
+            descripcion: metadata.descripcion,
+            media: metadata.media,
+            media_hash: metadata.media_hash,
+            copias: metadata.copias.map(u32::from),
+            emitido_en: metadata.emitido_en,
+            expira_en: metadata.expira_en,
+            comienzo_en: metadata.comienzo_en,
+            actualizado_en: metadata.actualizado_en,
+            extra: metadata.extra,
+            referencia: metadata.referencia,
+            referencia_hash: metadata.referencia_hash,
+        }
+    }
+}
+
+/// `Collectible` desplegado, con sus tokens creados en el mismo struct.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct CollectibleBase {
+    pub(crate) gate_id: GateId,
+    pub(crate) id_creador: AccountId,
+    pub(crate) cantidad_actual: u16,
+    pub(crate) tokens_creados: Vec<TokenId>,
+    pub(crate) comision: Fraction,
+    pub(crate) metadata: MetadataBase,
+}
+
+impl From<CollectibleBase> for Collectible {
+    fn from(ticket: CollectibleBase) -> Self {
+        Self {
+            gate_id: ticket.gate_id,
+            id_creador: ticket.id_creador,
+            cantidad_actual: Some(ticket.cantidad_actual.into()),
+            cantidad_creada: ticket.tokens_creados.len() as u32,
+            crear_hasta: None,
+            comision: ticket.comision,
+            cancelado: false,
+            venta_holandesa: None,
+            metadata: ticket.metadata.into(),
+            historial_metadata: Vec::new(),
+            detalles: None,
+        }
+    }
+}
+
+/// `Token` desplegado, sin `precio_venta` ni `metadata_token`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct TokenBase {
+    pub(crate) token_id: TokenId,
+    pub(crate) gate_id: GateId,
+    pub(crate) owner_id: AccountId,
+    pub(crate) created_at: Timestamp,
+    pub(crate) modified_at: Timestamp,
+    pub(crate) approvals: HashMap<AccountId, TokenApproval>,
+    pub(crate) approval_counter: U64,
+}
+
+impl From<TokenBase> for Token {
+    fn from(token: TokenBase) -> Self {
+        Self {
+            token_id: token.token_id,
+            gate_id: token.gate_id,
+            owner_id: token.owner_id,
+            created_at: token.created_at,
+            modified_at: token.modified_at,
+            approvals: token.approvals,
+            approval_counter: token.approval_counter,
+            precio_venta: None,
+            metadata_token: MetadataToken::default(),
+            metadata: Metadata::default(),
+        }
+    }
+}
+
+/// Estado desplegado de `ContratoNft`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct ContratoNftBase {
+    pub(crate) tickets: UnorderedMap<GateId, CollectibleBase>,
+    pub(crate) tickets_de_creador: LookupMap<AccountId, UnorderedSet<GateId>>,
+    pub(crate) tokens: UnorderedMap<TokenId, TokenBase>,
+    pub(crate) tokens_de_address: LookupMap<AccountId, UnorderedSet<TokenId>>,
+    pub(crate) id_admin: AccountId,
+    pub(crate) metadata: NFTContractMetadata,
+    pub(crate) fee_reventa: Fraction,
+    pub(crate) fee_reventa_id_address: AccountId,
+}
+
+pub(crate) fn assert_admin(id_admin: &AccountId) {
+    let pred_id = env::predecessor_account_id();
+    if pred_id != env::current_account_id() && &pred_id != id_admin {
+        Panic::AdminRestrictedOperation.panic();
+    }
+}
+
+/// Lee los valores de `map` con otro formato.
+/// Los maps se guardan solo por su prefijo, por lo que los valores se pueden reescribir
+/// en el mismo map con el formato actual.
+pub(crate) fn releer<K, V, W>(map: &UnorderedMap<K, V>) -> UnorderedMap<K, W>
+where
+    K: BorshSerialize + BorshDeserialize,
+    V: BorshSerialize + BorshDeserialize,
+    W: BorshSerialize + BorshDeserialize,
+{
+    BorshDeserialize::try_from_slice(&map.try_to_vec().unwrap()).unwrap()
+}
+
+/// Avance de una migracion por partes, ver `migrar`.
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub(crate) struct Migracion {
+    /// Cantidad de pasos ejecutados, cada paso migra un token creado de un ticket,
+    /// un ticket o un token.
+    pub(crate) pasos: u64,
+    /// Indice del proximo ticket a migrar.
+    pub(crate) ticket: u64,
+    /// Posicion en `tokens_creados` del proximo token creado del ticket `ticket`.
+    pub(crate) token_creado: u64,
+    /// Indice del proximo token a migrar, una vez migrados todos los tickets.
+    pub(crate) token: u64,
+}
+
+/// Ejecuta hasta `limite` pasos de la migracion de `contrato` desde `migracion`.
+/// Primero mueve los `tokens_creados` de cada ticket a `tokens_por_ticket`,
+/// guardando la edicion de cada token segun el orden en que se crearon,
+/// y reescribe el ticket con el formato actual.
+/// Luego reescribe cada token con su edicion y actualiza `siguiente_token_id`.
+/// Retorna si la migracion termino.
+pub(crate) fn migrar(contrato: &mut ContratoNft, migracion: &mut Migracion, limite: u64) -> bool {
+    let mut restantes = limite;
+    let mut ediciones: LookupMap<TokenId, u32> = LookupMap::new(Keys::EdicionesMigracion);
+
+    let tickets: UnorderedMap<GateId, CollectibleBase> = releer(&contrato.tickets);
+    while restantes > 0 && migracion.ticket < contrato.tickets.len() {
+        let gate_id = contrato.tickets.keys_as_vector().get(migracion.ticket).unwrap();
+        let anterior = tickets.get(&gate_id).unwrap();
+        let mut tids = contrato.tokens_por_ticket.get(&gate_id).unwrap_or_else(|| {
+            UnorderedSet::new(Keys::TokensPorTicketValor { hash_gate_id: crypto_hash(&gate_id) })
+        });
+        for token_id in anterior
+            .tokens_creados
+            .iter()
+            .skip(migracion.token_creado as usize)
+            .take(restantes as usize)
+        {
+            migracion.token_creado += 1;
+            tids.insert(token_id);
+            ediciones.insert(token_id, &(migracion.token_creado as u32));
+            restantes -= 1;
+        }
+        contrato.tokens_por_ticket.insert(&gate_id, &tids);
+        if restantes == 0 {
+            break;
+        }
+
+        reescribir(&mut contrato.tickets, &gate_id, &anterior.into());
+        migracion.ticket += 1;
+        migracion.token_creado = 0;
+        restantes -= 1;
+    }
+
+    let tokens: UnorderedMap<TokenId, TokenBase> = releer(&contrato.tokens);
+    while restantes > 0 && migracion.token < contrato.tokens.len() {
+        let token_id = contrato.tokens.keys_as_vector().get(migracion.token).unwrap();
+        let mut token: Token = tokens.get(&token_id).unwrap().into();
+        token.metadata_token.edicion = ediciones.remove(&token_id).unwrap_or_default();
+        contrato.siguiente_token_id = contrato.siguiente_token_id.max(token_id.0 + 1);
+        reescribir(&mut contrato.tokens, &token_id, &token);
+        migracion.token += 1;
+        restantes -= 1;
+    }
+
+    migracion.pasos += limite - restantes;
+    migracion.ticket == contrato.tickets.len() && migracion.token == contrato.tokens.len()
+}
+
+/// Reescribe el valor de `key` en `map` con el formato actual.
+/// Se usa `insert_raw` porque `insert` deserializa el valor anterior con el formato actual.
+fn reescribir<K, V>(map: &mut UnorderedMap<K, V>, key: &K, value: &V)
+where
+    K: BorshSerialize + BorshDeserialize,
+    V: BorshSerialize + BorshDeserialize,
+{
+    map.insert_raw(&key.try_to_vec().unwrap(), &value.try_to_vec().unwrap());
+}
@@ -1,57 +1,88 @@
-use core_nft::{
-    gate::{GateId, ValidGateId},
+use crate::{
+    context::{alice, bob, charlie, fee_reventa_id_address, gate_id, market, mintgate_admin, nft},
+    market::{ContratoMercado, FiltroVenta, Orden, SelfCallback, TokenEnVenta},
+    migraciones::{CollectibleBase, ContratoNftBase, MetadataBase, TokenBase},
     mock_context,
-    mocked_context::{
-        alice, any, bob, charlie, gate_id, market, mintgate_admin, mintgate_fee_account_id,
-    },
     nep171::NonFungibleTokenCore,
     nep177::NFTContractMetadata,
-    nep177::NonFungibleTokenMetadata,
-    nep178::NonFungibleTokenApprovalMgmt,
+    nep178::{NonFungibleTokenApprovalMgmt, NonFungibleTokenApprovalsReceiver},
     nep181::NonFungibleTokenEnumeration,
-    NftApproveMsg, TokenApproval, TokenId,
+    CategoriaEvento, ContratoNft, Coordenadas, DetallesEvento, GateId, MarketApproveMsg, Metadata,
+    NftApproveMsg, PrecioHolandes, SelfCallback as _, Token, TokenApproval, TokenId, ValidGateId,
 };
-use mg_nft::NftContract;
 use near_sdk::{
+    borsh::{BorshDeserialize, BorshSerialize},
+    collections::UnorderedMap,
+    env,
     json_types::{ValidAccountId, U128, U64},
     serde_json,
+    test_utils::{get_created_receipts, get_logs, testing_env_with_promise_results},
+    PromiseResult,
 };
 use std::{
+    collections::HashMap,
     convert::TryInto,
     ops::{Deref, DerefMut},
 };
 
-// mock_context!();
+mock_context!();
+
+const MEDIA_HASH: &str = "chyVJa3i6okD00PvJc9oub9KsKrVa7ewH75I0JvH/PQ=";
+const REFERENCIA_HASH: &str = "P/bAVyO7Bp0ZlTNAMg+pUS8L5YR0JwPmAibe0ou0OGE=";
 
 struct NftContractChecker {
-    contrato: NftContract,
+    contrato: ContratoNft,
     claimed_tokens: Vec<TokenId>,
 }
 
-impl MockedContext<NftContractChecker> {
+impl Deref for NftContractChecker {
+    type Target = ContratoNft;
+
+    fn deref(&self) -> &Self::Target {
+        &self.contrato
+    }
+}
+
+impl DerefMut for NftContractChecker {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.contrato
+    }
+}
 
+impl MockedContext<NftContractChecker> {
     fn inicializar_contrato(
         min_royalty: &str,
         max_royalty: &str,
         metadata: NFTContractMetadata,
     ) -> MockedContext<NftContractChecker> {
         MockedContext::new(|| NftContractChecker {
-            contrato: NftContract::init(
+            contrato: ContratoNft::init(
                 mintgate_admin(),
                 metadata,
                 min_royalty.parse().unwrap(),
                 max_royalty.parse().unwrap(),
                 "25/1000".parse().unwrap(),
-                mintgate_fee_account_id(),
+                fee_reventa_id_address(),
             ),
             claimed_tokens: Vec::new(),
         })
     }
-    
+
     fn init() -> MockedContext<NftContractChecker> {
-        init_contract("5/100", "30/100", metadata(base_uri()))
+        Self::inicializar_contrato("5/100", "30/100", Self::metadata(None))
+    }
+
+    fn contrato() -> ContratoNft {
+        ContratoNft::init(
+            mintgate_admin(),
+            Self::metadata(None),
+            "5/100".parse().unwrap(),
+            "30/100".parse().unwrap(),
+            "25/1000".parse().unwrap(),
+            fee_reventa_id_address(),
+        )
     }
-    
+
     fn metadata(base_uri: Option<String>) -> NFTContractMetadata {
         NFTContractMetadata {
             spec: "my-tickets".to_string(),
@@ -59,58 +90,59 @@ impl MockedContext<NftContractChecker> {
             symbol: "MTK".to_string(),
             icon: None,
             base_uri,
-            reference: None,
-            reference_hash: None,
+            referencia: None,
+            referencia_hash: None,
         }
     }
+
     fn crear_tickets(
         &mut self,
         creador_id: ValidAccountId,
         gate_id: ValidGateId,
-        cantidad: u16,
+        cantidad: u32,
         comision: &str,
     ) {
-        let tickets_por_owner = self.get_collectibles_by_creator(creator_id.clone());
+        let tickets_por_owner = self.get_tickets_de_creador(creador_id.clone());
 
         println!("Tickets: `{}`, supply {}", gate_id, cantidad);
 
         let comision = comision.parse().unwrap();
-        self.contrato.crear_tickets(
+        self.contrato.crear_ticket(
             creador_id.clone(),
             gate_id.clone(),
             "My tickets".to_string(),
             "descripcion".to_string(),
-            cantidad,
+            Some(cantidad),
             comision,
-            Some("texto".to_string()),
-            Some("111".to_string()),
-            Some("ref".to_string()),
-            Some("222".to_string()),
+            Some("ipfs://media".to_string()),
+            Some(MEDIA_HASH.to_string()),
+            Some("https://ref".to_string()),
+            Some(REFERENCIA_HASH.to_string()),
+            None,
+            None,
+            None,
         );
 
-        let ticket = self.contrato.get_collectible_by_gate_id(gate_id.clone()).unwrap();
-        assert_eq!(ticket.creator_id, creator_id.to_string());
+        let ticket = self.contrato.get_ticket_por_id(gate_id.clone()).unwrap();
+        assert_eq!(ticket.id_creador, creador_id.to_string());
         assert_eq!(&ticket.gate_id, gate_id.as_ref());
-        assert_eq!(ticket.current_supply, supply);
-        assert_eq!(ticket.minted_tokens.len(), 0);
-        assert_eq!(ticket.royalty, royalty);
-        assert_eq!(ticket.metadata.media, Some("media".to_string()));
-        assert_eq!(ticket.metadata.media_hash, Some("111".to_string()));
-        assert_eq!(ticket.metadata.reference, Some("ref".to_string()));
-        assert_eq!(ticket.metadata.reference_hash, Some("222".to_string()));
+        assert_eq!(ticket.cantidad_actual, Some(cantidad));
+        assert_eq!(ticket.cantidad_creada, 0);
+        assert_eq!(self.get_tokens_de_ticket(gate_id.clone(), None, None).len(), 0);
+        assert_eq!(ticket.comision, comision);
+        assert_eq!(ticket.metadata.media, Some("ipfs://media".to_string()));
+        assert_eq!(ticket.metadata.media_hash, Some(MEDIA_HASH.to_string()));
+        assert_eq!(ticket.metadata.referencia, Some("https://ref".to_string()));
+        assert_eq!(ticket.metadata.referencia_hash, Some(REFERENCIA_HASH.to_string()));
 
-        assert_eq!(
-            self.get_collectibles_by_creator(creador_id).len(),
-            tickets_por_owner.len() + 1
-        );
+        assert_eq!(self.get_tickets_de_creador(creador_id).len(), tickets_por_owner.len() + 1);
     }
 
     fn comprar_tickets(&mut self, gate_id: ValidGateId) -> TokenId {
-        let total_supply = self.
-        .nft_total_supply().0;
+        let total_supply = self.contrato.nft_total_supply().0;
         let supply_por_owner = self.contrato.nft_supply_for_owner(self.pred_id()).0;
 
-        let token_id = self.contrato.claim_token(gate_id.clone());
+        let token_id = self.contrato.comprar_token(gate_id.clone());
 
         assert_eq!(self.contrato.nft_total_supply(), U64(total_supply + 1));
         assert_eq!(self.contrato.nft_supply_for_owner(self.pred_id()), U64(supply_por_owner + 1));
@@ -121,12 +153,1774 @@ impl MockedContext<NftContractChecker> {
         assert_eq!(token.approvals.len(), 0);
         assert_eq!(token.approval_counter, U64(0));
 
-        let ticket = self.contrato.get_collectible_by_gate_id(gate_id.clone()).unwrap();
-        assert_eq!(token.metadata, ticket.metadata);
+        let ticket = self.contrato.get_ticket_por_id(gate_id.clone()).unwrap();
+        assert_eq!(token.metadata_token.edicion, ticket.cantidad_creada);
+        assert_eq!(token.metadata, ticket.metadata.con_token(&token.metadata_token));
 
         self.claimed_tokens.insert(0, token_id);
         token_id
     }
 
+    fn aprobar_token(&mut self, token_id: TokenId, account_id: ValidAccountId, min_precio: u128) {
+        let msg = NftApproveMsg { min_precio: U128(min_precio), oferta_id: None, expira_en: None };
+        self.contrato.nft_approve(
+            token_id,
+            account_id.clone(),
+            Some(serde_json::to_string(&msg).unwrap()),
+        );
+
+        let token = self.nft_token(token_id).unwrap();
+        assert_eq!(
+            token.approvals.get(account_id.as_ref()),
+            Some(&TokenApproval::new(token.approval_counter.0, U128(min_precio)))
+        );
+    }
+}
+
+/// Lleva el estado de `contrato` al formato desplegado,
+/// con los tickets y los tokens guardados en el mismo prefijo.
+fn estado_base(mut contrato: ContratoNft) -> ContratoNftBase {
+    let actuales = contrato.tickets.to_vec();
+    contrato.tickets.clear();
+    let mut tickets: UnorderedMap<GateId, CollectibleBase> =
+        BorshDeserialize::try_from_slice(&contrato.tickets.try_to_vec().unwrap()).unwrap();
+    for (gate_id, ticket) in actuales {
+        let mut tokens_creados = Vec::new();
+        if let Some(mut tids) = contrato.tokens_por_ticket.remove(&gate_id) {
+            tokens_creados = tids.to_vec();
+            tids.clear();
+        }
+        let anterior = CollectibleBase {
+            gate_id: ticket.gate_id,
+            id_creador: ticket.id_creador,
+            cantidad_actual: ticket.cantidad_actual.unwrap() as u16,
+            tokens_creados,
+            comision: ticket.comision,
+            metadata: metadata_base(ticket.metadata),
+        };
+        tickets.insert(&gate_id, &anterior);
+    }
+
+    let actuales = contrato.tokens.to_vec();
+    contrato.tokens.clear();
+    let mut tokens: UnorderedMap<TokenId, TokenBase> =
+        BorshDeserialize::try_from_slice(&contrato.tokens.try_to_vec().unwrap()).unwrap();
+    for (token_id, token) in actuales {
+        let anterior = TokenBase {
+            token_id: token.token_id,
+            gate_id: token.gate_id,
+            owner_id: token.owner_id,
+            created_at: token.created_at,
+            modified_at: token.modified_at,
+            approvals: token.approvals,
+            approval_counter: token.approval_counter,
+        };
+        tokens.insert(&token_id, &anterior);
+    }
+
+    ContratoNftBase {
+        tickets,
+        tickets_de_creador: contrato.tickets_de_creador,
+        tokens,
+        tokens_de_address: contrato.tokens_de_address,
+        id_admin: contrato.id_admin,
+        metadata: contrato.metadata,
+        fee_reventa: contrato.fee_reventa,
+        fee_reventa_id_address: contrato.fee_reventa_id_address,
+    }
+}
+
+fn metadata_base(metadata: Metadata) -> MetadataBase {
+    MetadataBase {
+        titulo: metadata.titulo,
+        descripcion: metadata.descripcion,
+        media: metadata.media,
+        media_hash: metadata.media_hash,
+        copias: metadata.copias.map(|copias| copias as u16),
+        emitido_en: metadata.emitido_en,
+        expira_en: metadata.expira_en,
+        comienzo_en: metadata.comienzo_en,
+        actualizado_en: metadata.actualizado_en,
+        extra: metadata.extra,
+        referencia: metadata.referencia,
+        referencia_hash: metadata.referencia_hash,
+    }
+}
+
+#[test]
+fn reventa_por_mercado_con_aprobacion() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    let mut token_id = U64(0);
+    ctx.run_as(bob(), |ctx| {
+        token_id = ctx.comprar_tickets(gate_id(1));
+        ctx.aprobar_token(token_id, market(), 1000);
+    });
+
+    ctx.run_as(market(), |ctx| {
+        let payout = ctx
+            .contrato
+            .nft_transfer_payout(charlie(), token_id, Some(U64(1)), None, Some(U128(1000)))
+            .unwrap();
+        assert_eq!(payout.get(alice().as_ref()), Some(&U128(50)));
+        assert_eq!(payout.get(fee_reventa_id_address().as_ref()), Some(&U128(25)));
+        assert_eq!(payout.get(bob().as_ref()), Some(&U128(925)));
+
+        let token = ctx.nft_token(token_id).unwrap();
+        assert_eq!(token.owner_id, charlie().to_string());
+        assert_eq!(token.approvals.len(), 0);
+        assert_eq!(token.precio_venta, Some(U128(1000)));
+    });
+}
+
+#[test]
+fn actualizar_precio_aprobacion_por_el_dueno() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    ctx.run_as(bob(), |ctx| {
+        let token_id = ctx.comprar_tickets(gate_id(1));
+        ctx.aprobar_token(token_id, market(), 1000);
+        ctx.contrato.actualizar_precio_aprobacion(token_id, market(), U128(800));
+
+        let token = ctx.nft_token(token_id).unwrap();
+        assert_eq!(token.approvals.get(market().as_ref()), Some(&TokenApproval::new(1, U128(800))));
+    });
+}
+
+#[test]
+fn actualizar_precio_aprobacion_rechazado_por_el_market() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    let mut token_id = U64(0);
+    ctx.run_as(bob(), |ctx| {
+        token_id = ctx.comprar_tickets(gate_id(1));
+        ctx.aprobar_token(token_id, market(), 1000);
+        ctx.contrato.actualizar_precio_aprobacion(token_id, market(), U128(800));
+    });
+
+    testing_env_with_promise_results(ctx.context.clone(), PromiseResult::Failed);
+    let market_id = market().to_string();
+    ctx.contract.resolve_price_update(token_id, market_id, U64(1), U128(800), U128(1000));
+    let token = ctx.nft_token(token_id).unwrap();
+    assert_eq!(token.approvals.get(market().as_ref()), Some(&TokenApproval::new(1, U128(1000))));
+}
+
+#[test]
+fn actualizar_precio_aprobacion_aceptado_por_el_market() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    let mut token_id = U64(0);
+    ctx.run_as(bob(), |ctx| {
+        token_id = ctx.comprar_tickets(gate_id(1));
+        ctx.aprobar_token(token_id, market(), 1000);
+        ctx.contrato.actualizar_precio_aprobacion(token_id, market(), U128(800));
+        let nft_id = ctx.context.current_account_id.clone();
+        assert_eq!(
+            llamadas(),
+            vec![
+                (market().to_string(), "nft_on_price_update".to_string()),
+                (nft_id, "resolve_price_update".to_string())
+            ]
+        );
+    });
+
+    testing_env_with_promise_results(ctx.context.clone(), PromiseResult::Successful(vec![]));
+    let market_id = market().to_string();
+    ctx.contract.resolve_price_update(token_id, market_id, U64(1), U128(800), U128(1000));
+    let token = ctx.nft_token(token_id).unwrap();
+    assert_eq!(token.approvals.get(market().as_ref()), Some(&TokenApproval::new(1, U128(800))));
+}
+
+#[test]
+#[should_panic(expected = "TokenIdNotOwnedBy")]
+fn actualizar_precio_aprobacion_por_el_market() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    let mut token_id = U64(0);
+    ctx.run_as(bob(), |ctx| {
+        token_id = ctx.comprar_tickets(gate_id(1));
+        ctx.aprobar_token(token_id, market(), 1000);
+    });
+
+    ctx.run_as(market(), |ctx| {
+        ctx.contrato.actualizar_precio_aprobacion(token_id, market(), U128(1));
+    });
+}
+
+#[test]
+fn transferencia_del_dueno_ignora_enforce_approval_id() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    ctx.run_as(bob(), |ctx| {
+        let token_id = ctx.comprar_tickets(gate_id(1));
+        ctx.contrato.nft_transfer(charlie(), token_id, Some(U64(7)), None);
+        assert_eq!(ctx.nft_token(token_id).unwrap().owner_id, charlie().to_string());
+    });
+}
+
+#[test]
+#[should_panic(expected = "EnforceApprovalFailed")]
+fn reventa_por_mercado_con_aprobacion_invalida() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    let mut token_id = U64(0);
+    ctx.run_as(bob(), |ctx| {
+        token_id = ctx.comprar_tickets(gate_id(1));
+        ctx.aprobar_token(token_id, market(), 1000);
+    });
+
+    ctx.run_as(market(), |ctx| {
+        ctx.contrato.nft_transfer_payout(charlie(), token_id, Some(U64(2)), None, Some(U128(1000)));
+    });
+}
+
+#[test]
+#[should_panic(expected = "SenderNotAuthToTransfer")]
+fn transferencia_sin_aprobacion() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    let mut token_id = U64(0);
+    ctx.run_as(bob(), |ctx| {
+        token_id = ctx.comprar_tickets(gate_id(1));
+    });
+
+    ctx.run_as(market(), |ctx| {
+        ctx.contrato.nft_transfer(charlie(), token_id, Some(U64(1)), None);
+    });
+}
+
+#[test]
+#[should_panic(expected = "PriceBelowMinimum")]
+fn reventa_por_mercado_debajo_del_precio_minimo() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    let mut token_id = U64(0);
+    ctx.run_as(bob(), |ctx| {
+        token_id = ctx.comprar_tickets(gate_id(1));
+        ctx.aprobar_token(token_id, market(), 1000);
+    });
+
+    ctx.run_as(market(), |ctx| {
+        ctx.contrato.nft_transfer_payout(charlie(), token_id, Some(U64(1)), None, Some(U128(999)));
+    });
+}
+
+#[test]
+fn aprobar_por_lote_en_varios_markets() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    ctx.run_as(bob(), |ctx| {
+        let token_1 = ctx.comprar_tickets(gate_id(1));
+        let token_2 = ctx.comprar_tickets(gate_id(1));
+        ctx.contrato.aprobar_por_lote(vec![
+            (token_1, market(), U128(1000)),
+            (token_2, charlie(), U128(2000)),
+        ]);
+
+        let token = ctx.nft_token(token_1).unwrap();
+        assert_eq!(
+            token.approvals.get(market().as_ref()),
+            Some(&TokenApproval::new(token.approval_counter.0, U128(1000)))
+        );
+        let token = ctx.nft_token(token_2).unwrap();
+        assert_eq!(
+            token.approvals.get(charlie().as_ref()),
+            Some(&TokenApproval::new(token.approval_counter.0, U128(2000)))
+        );
+    });
+}
+
+#[test]
+fn transferencia_por_lote() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    ctx.run_as(bob(), |ctx| {
+        let token_1 = ctx.comprar_tickets(gate_id(1));
+        let token_2 = ctx.comprar_tickets(gate_id(1));
+        ctx.aprobar_token(token_1, market(), 1000);
+        ctx.contrato.nft_batch_transfer(vec![
+            (charlie(), token_1),
+            (alice(), token_2),
+            (alice(), U64(100)),
+        ]);
+
+        let token = ctx.nft_token(token_1).unwrap();
+        assert_eq!(token.owner_id, charlie().to_string());
+        assert_eq!(token.approvals.len(), 0);
+        assert_eq!(ctx.nft_token(token_2).unwrap().owner_id, alice().to_string());
+        assert_eq!(ctx.nft_supply_for_owner(bob()), U64(0));
+    });
+}
+
+#[test]
+fn revocar_por_lote() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    ctx.run_as(bob(), |ctx| {
+        let token_1 = ctx.comprar_tickets(gate_id(1));
+        let token_2 = ctx.comprar_tickets(gate_id(1));
+        ctx.aprobar_token(token_1, market(), 1000);
+        ctx.aprobar_token(token_2, market(), 2000);
+        ctx.contrato.revocar_por_lote(vec![token_1, token_2], market());
+
+        assert_eq!(ctx.nft_token(token_1).unwrap().approvals.len(), 0);
+        assert_eq!(ctx.nft_token(token_2).unwrap().approvals.len(), 0);
+    });
+}
+
+#[test]
+fn revocar_por_lote_con_notificacion_fallida() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    let mut token_id = U64(0);
+    ctx.run_as(bob(), |ctx| {
+        token_id = ctx.comprar_tickets(gate_id(1));
+        ctx.aprobar_token(token_id, market(), 1000);
+        ctx.contrato.revocar_por_lote(vec![token_id], market());
+    });
+
+    testing_env_with_promise_results(ctx.context.clone(), PromiseResult::Failed);
+    ctx.contract.resolve_batch_revoke(vec![market().to_string()], vec![]);
+    assert!(get_logs().contains(&"Market `market` failed to process the revocations".to_string()));
+    assert_eq!(ctx.nft_token(token_id).unwrap().approvals.len(), 0);
+}
+
+#[test]
+fn quemar_token() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    ctx.run_as(bob(), |ctx| {
+        let token_id = ctx.comprar_tickets(gate_id(1));
+        ctx.comprar_tickets(gate_id(1));
+        ctx.contrato.nft_burn(token_id);
+
+        assert!(ctx.nft_token(token_id).is_none());
+        assert_eq!(ctx.nft_total_supply(), U64(1));
+        assert_eq!(ctx.nft_supply_for_owner(bob()), U64(1));
+        let tokens = ctx.get_tokens_de_ticket(gate_id(1), None, None);
+        assert!(tokens.iter().all(|token| token.token_id != token_id));
+    });
+}
+
+#[test]
+fn quemar_por_lote_de_ticket_cancelado() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    let mut token_ids = Vec::new();
+    ctx.run_as(bob(), |ctx| {
+        token_ids.push(ctx.comprar_tickets(gate_id(1)));
+        token_ids.push(ctx.comprar_tickets(gate_id(1)));
+    });
+
+    ctx.run_as(alice(), |ctx| {
+        ctx.contrato.cancelar_ticket(gate_id(1));
+        ctx.contrato.quemar_por_lote(gate_id(1), token_ids.clone());
+
+        assert_eq!(ctx.nft_total_supply(), U64(0));
+        assert_eq!(ctx.nft_supply_for_owner(bob()), U64(0));
+        assert_eq!(ctx.get_tokens_de_ticket(gate_id(1), None, None).len(), 0);
+        ctx.contrato.borrar_ticket(gate_id(1));
+    });
+}
+
+#[test]
+fn quemar_por_lote_de_ticket_vencido() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    let mut token_id = U64(0);
+    ctx.run_as(bob(), |ctx| {
+        token_id = ctx.comprar_tickets(gate_id(1));
+    });
+
+    ctx.run_as(alice(), |ctx| {
+        ctx.contrato.set_expiracion(gate_id(1), 1000);
+        let ticket = ctx.get_ticket_por_id(gate_id(1)).unwrap();
+        assert_eq!(ticket.metadata.expira_en, Some(1000));
+        assert_eq!(ticket.historial_metadata.len(), 1);
+    });
+
+    ctx.context.block_timestamp = 1000 * 1_000_000;
+    ctx.run_as(alice(), |ctx| {
+        ctx.contrato.quemar_por_lote(gate_id(1), vec![token_id]);
+        assert_eq!(ctx.nft_total_supply(), U64(0));
+    });
+}
+
+#[test]
+#[should_panic(expected = "GateIdNotBurnable")]
+fn quemar_por_lote_de_ticket_vigente() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    let mut token_id = U64(0);
+    ctx.run_as(bob(), |ctx| {
+        token_id = ctx.comprar_tickets(gate_id(1));
+    });
+
+    ctx.run_as(alice(), |ctx| {
+        ctx.contrato.quemar_por_lote(gate_id(1), vec![token_id]);
+    });
+}
+
+#[test]
+fn comprar_luego_de_quemar_no_reutiliza_ids() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    ctx.run_as(bob(), |ctx| {
+        let token_0 = ctx.comprar_tickets(gate_id(1));
+        let token_1 = ctx.comprar_tickets(gate_id(1));
+        ctx.contrato.nft_burn(token_0);
+
+        let token_2 = ctx.comprar_tickets(gate_id(1));
+        assert_eq!(token_2, U64(2));
+        assert_eq!(ctx.nft_token(token_1).unwrap().owner_id, bob().to_string());
+        assert_eq!(ctx.nft_total_supply(), U64(2));
+    });
+}
+
+#[test]
+fn tokens_de_ticket_paginados() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+
+    ctx.run_as(bob(), |ctx| {
+        for _ in 0..5 {
+            ctx.comprar_tickets(gate_id(1));
+        }
+        let tokens = ctx.get_tokens_de_ticket(gate_id(1), Some(U64(1)), Some(3));
+        let token_ids = tokens.iter().map(|token| token.token_id).collect::<Vec<_>>();
+        assert_eq!(token_ids, vec![U64(1), U64(2), U64(3)]);
+    });
+}
+
+#[test]
+#[should_panic(expected = "GateIdHasTokens")]
+fn borrar_ticket_con_tokens() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+    ctx.run_as(bob(), |ctx| {
+        ctx.comprar_tickets(gate_id(1));
+    });
+    ctx.run_as(alice(), |ctx| ctx.contrato.borrar_ticket(gate_id(1)));
+}
+
+#[test]
+fn crear_ticket_con_cantidad_mayor_a_u16() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 100_000, "5/100"));
+    ctx.run_as(bob(), |ctx| {
+        ctx.comprar_tickets(gate_id(1));
+        let ticket = ctx.get_ticket_por_id(gate_id(1)).unwrap();
+        assert_eq!(ticket.cantidad_actual, Some(99_999));
+        assert_eq!(ticket.metadata.copias, Some(100_000));
+    });
+}
+
+#[test]
+fn comprar_ticket_de_edicion_abierta() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| {
+        ctx.crear_ticket(
+            alice(),
+            gate_id(1),
+            "My tickets".to_string(),
+            "descripcion".to_string(),
+            None,
+            "5/100".parse().unwrap(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+    });
+    ctx.run_as(bob(), |ctx| {
+        for _ in 0..3 {
+            ctx.comprar_tickets(gate_id(1));
+        }
+        let ticket = ctx.get_ticket_por_id(gate_id(1)).unwrap();
+        assert_eq!(ticket.cantidad_actual, None);
+        assert_eq!(ticket.cantidad_creada, 3);
+        assert_eq!(ticket.metadata.copias, None);
+    });
+}
+
+#[test]
+#[should_panic(expected = "MintingEnded")]
+fn comprar_ticket_luego_de_crear_hasta() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| {
+        let crear_hasta = env::block_timestamp() / 1_000_000 + 10;
+        ctx.crear_ticket(
+            alice(),
+            gate_id(1),
+            "My tickets".to_string(),
+            "descripcion".to_string(),
+            None,
+            "5/100".parse().unwrap(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(crear_hasta),
+            None,
+        );
+    });
+    ctx.run_as(bob(), |ctx| {
+        ctx.comprar_tickets(gate_id(1));
+        ctx.context.block_timestamp += 10 * 1_000_000;
+        ctx.comprar_tickets(gate_id(1));
+    });
+}
+
+#[test]
+fn agregar_y_quitar_cantidad() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+    ctx.run_as(bob(), |ctx| {
+        ctx.comprar_tickets(gate_id(1));
+    });
+    ctx.run_as(alice(), |ctx| {
+        ctx.agregar_cantidad(gate_id(1), 5);
+        let ticket = ctx.get_ticket_por_id(gate_id(1)).unwrap();
+        assert_eq!(ticket.cantidad_actual, Some(14));
+        assert_eq!(ticket.metadata.copias, Some(15));
+
+        ctx.quitar_cantidad(gate_id(1), 14);
+        let ticket = ctx.get_ticket_por_id(gate_id(1)).unwrap();
+        assert_eq!(ticket.cantidad_actual, Some(0));
+        assert_eq!(ticket.cantidad_creada, 1);
+        assert_eq!(ticket.metadata.copias, Some(1));
+    });
+}
+
+#[test]
+#[should_panic(expected = "SupplyBelowMinted")]
+fn quitar_cantidad_debajo_de_tokens_creados() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+    ctx.run_as(bob(), |ctx| {
+        ctx.comprar_tickets(gate_id(1));
+    });
+    ctx.run_as(alice(), |ctx| ctx.quitar_cantidad(gate_id(1), 10));
+}
+
+fn crear_ticket_holandes(ctx: &mut MockedContext<NftContractChecker>, reembolso: bool) {
+    let precio = PrecioHolandes {
+        precio_inicial: U128(1000),
+        precio_minimo: U128(400),
+        comienzo_en: 0,
+        intervalo: 10,
+        decremento: U128(100),
+        reembolso,
+    };
+    ctx.run_as(mintgate_admin(), |ctx| {
+        ctx.crear_ticket(
+            alice(),
+            gate_id(1),
+            "My tickets".to_string(),
+            "descripcion".to_string(),
+            Some(2),
+            "5/100".parse().unwrap(),
+            None,
+            None,
+            None,
+            None,
+            Some(precio),
+            None,
+            None,
+        );
+    });
+}
+
+#[test]
+#[should_panic(expected = "DutchAuctionStarted")]
+fn agregar_cantidad_a_venta_holandesa_comenzada() {
+    let mut ctx = MockedContext::init();
+    crear_ticket_holandes(&mut ctx, true);
+    ctx.run_as(bob(), |ctx| {
+        ctx.attach_deposit(1000).comprar_token(gate_id(1));
+    });
+    ctx.run_as(alice(), |ctx| ctx.agregar_cantidad(gate_id(1), 1));
+}
+
+#[test]
+fn reclamar_reembolso_y_retirar_ventas() {
+    let mut ctx = MockedContext::init();
+    crear_ticket_holandes(&mut ctx, true);
+    ctx.run_as(bob(), |ctx| {
+        ctx.attach_deposit(1000).comprar_token(gate_id(1));
+        assert_eq!(transferencias(), vec![]);
+    });
+
+    ctx.context.block_timestamp = 30 * 1_000_000;
+    ctx.run_as(charlie(), |ctx| {
+        assert_eq!(ctx.get_precio_actual(gate_id(1)), Some(U128(700)));
+        ctx.attach_deposit(800).comprar_token(gate_id(1));
+        assert_eq!(transferencias(), vec![(charlie().to_string(), 100)]);
+    });
+
+    ctx.attach_deposit(0);
+    ctx.run_as(bob(), |ctx| {
+        assert_eq!(ctx.reclamar_reembolso(gate_id(1)), U128(300));
+        assert_eq!(transferencias(), vec![(bob().to_string(), 300)]);
+    });
+    ctx.run_as(alice(), |ctx| {
+        assert_eq!(ctx.retirar_ventas(gate_id(1)), U128(1400));
+        assert_eq!(transferencias(), vec![(alice().to_string(), 1400)]);
+        assert_eq!(ctx.retirar_ventas(gate_id(1)), U128(0));
+        assert_eq!(transferencias(), vec![]);
+    });
+}
+
+#[test]
+#[should_panic(expected = "NoRebateAvailable")]
+fn reclamar_reembolso_dos_veces() {
+    let mut ctx = MockedContext::init();
+    crear_ticket_holandes(&mut ctx, true);
+    ctx.run_as(bob(), |ctx| {
+        ctx.attach_deposit(1000).comprar_token(gate_id(1));
+    });
+    ctx.context.block_timestamp = 30 * 1_000_000;
+    ctx.run_as(charlie(), |ctx| {
+        ctx.attach_deposit(700).comprar_token(gate_id(1));
+    });
+
+    ctx.attach_deposit(0);
+    ctx.run_as(bob(), |ctx| {
+        ctx.reclamar_reembolso(gate_id(1));
+        ctx.reclamar_reembolso(gate_id(1));
+    });
+}
+
+#[test]
+#[should_panic(expected = "ClearingPriceNotReached")]
+fn reclamar_reembolso_antes_del_precio_final() {
+    let mut ctx = MockedContext::init();
+    crear_ticket_holandes(&mut ctx, true);
+    ctx.run_as(bob(), |ctx| {
+        ctx.attach_deposit(1000).comprar_token(gate_id(1));
+    });
+    ctx.attach_deposit(0);
+    ctx.run_as(bob(), |ctx| {
+        ctx.reclamar_reembolso(gate_id(1));
+    });
+}
+
+#[test]
+fn comprar_venta_holandesa_sin_reembolso() {
+    let mut ctx = MockedContext::init();
+    crear_ticket_holandes(&mut ctx, false);
+    ctx.context.block_timestamp = 100 * 1_000_000;
+    ctx.run_as(bob(), |ctx| {
+        ctx.attach_deposit(1000).comprar_token(gate_id(1));
+        let mut pagos = transferencias();
+        pagos.sort();
+        assert_eq!(pagos, vec![(alice().to_string(), 400), (bob().to_string(), 600)]);
+    });
+}
+
+#[test]
+fn actualizar_ticket() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+    ctx.run_as(alice(), |ctx| {
+        let anterior = ctx.get_ticket_por_id(gate_id(1)).unwrap().metadata;
+        ctx.actualizar_ticket(
+            gate_id(1),
+            Some("Mis tickets".to_string()),
+            None,
+            Some("ar://media2".to_string()),
+            Some("ojvsPx/Z+5LOP11/QV2wd0lwf3xT2suyvVcLaMFPi9M=".to_string()),
+            None,
+            None,
+        );
+
+        let ticket = ctx.get_ticket_por_id(gate_id(1)).unwrap();
+        assert_eq!(ticket.metadata.titulo, Some("Mis tickets".to_string()));
+        assert_eq!(ticket.metadata.descripcion, anterior.descripcion);
+        assert_eq!(ticket.metadata.media, Some("ar://media2".to_string()));
+        assert_eq!(ticket.metadata.referencia, anterior.referencia);
+        assert!(ticket.metadata.actualizado_en.is_some());
+        assert_eq!(ticket.historial_metadata.len(), 1);
+        assert_eq!(ticket.historial_metadata[0].hash, anterior.hash());
+    });
+}
+
+#[test]
+#[should_panic(expected = "`media` changed without a new `media_hash`")]
+fn actualizar_media_sin_hash() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+    ctx.run_as(alice(), |ctx| {
+        ctx.actualizar_ticket(
+            gate_id(1),
+            None,
+            None,
+            Some("ar://media2".to_string()),
+            None,
+            None,
+            None,
+        )
+    });
+}
+
+#[test]
+fn actualizar_titulo_de_ticket_con_media_anterior() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+    let gate_id_1 = gate_id(1).to_string();
+    let mut ticket = ctx.contrato.tickets.get(&gate_id_1).unwrap();
+    ticket.metadata.media = Some("http://media".to_string());
+    ctx.contrato.tickets.insert(&gate_id_1, &ticket);
+
+    ctx.run_as(alice(), |ctx| {
+        let titulo = Some("Mis tickets".to_string());
+        let media = Some("http://media".to_string());
+        ctx.actualizar_ticket(gate_id(1), titulo, None, media, None, None, None);
+
+        let ticket = ctx.get_ticket_por_id(gate_id(1)).unwrap();
+        assert_eq!(ticket.metadata.titulo, Some("Mis tickets".to_string()));
+        assert_eq!(ticket.metadata.media, Some("http://media".to_string()));
+    });
+}
+
+#[test]
+#[should_panic(expected = "must be an ipfs, ar or https URL")]
+fn actualizar_media_con_esquema_invalido() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+    ctx.run_as(alice(), |ctx| {
+        let media = Some("http://media2".to_string());
+        let hash = Some(MEDIA_HASH.to_string());
+        ctx.actualizar_ticket(gate_id(1), None, None, media, hash, None, None);
+    });
+}
+
+#[test]
+#[should_panic(expected = "CreatorRestrictedOperation")]
+fn actualizar_ticket_de_otro_creador() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+    ctx.run_as(bob(), |ctx| {
+        ctx.actualizar_ticket(
+            gate_id(1),
+            Some("Mis tickets".to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    });
+}
+
+#[test]
+fn metadata_por_token() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.crear_tickets(alice(), gate_id(1), 10, "5/100"));
+    let mut token_id = U64(0);
+    ctx.run_as(bob(), |ctx| {
+        ctx.comprar_tickets(gate_id(1));
+        token_id = ctx.comprar_tickets(gate_id(1));
+    });
+    ctx.run_as(alice(), |ctx| {
+        ctx.asignar_asiento(token_id, Some("F12".to_string()), Some("VIP".to_string()))
+    });
+    ctx.run_as(bob(), |ctx| {
+        ctx.asignar_nombre_titular(token_id, Some("Bob".to_string()));
+
+        let token = ctx.nft_token(token_id).unwrap();
+        assert_eq!(token.metadata.titulo, Some("My tickets #2 of 10".to_string()));
+        let extra: serde_json::Value =
+            serde_json::from_str(&token.metadata.extra.unwrap()).unwrap();
+        assert_eq!(
+            extra,
+            serde_json::json!({
+                "edicion": 2,
+                "asiento": "F12",
+                "nivel": "VIP",
+                "nombre_titular": "Bob",
+            })
+        );
+        assert!(ctx.nft_tokens_for_owner(bob(), None, None)[0].metadata.extra.is_some());
+
+        ctx.nft_transfer(charlie(), token_id, None, None);
+        let token = ctx.nft_token(token_id).unwrap();
+        assert_eq!(token.metadata_token.asiento, Some("F12".to_string()));
+        assert_eq!(token.metadata_token.nombre_titular, None);
+    });
+}
+
+#[test]
+#[should_panic(expected = "`media_hash` is required when `media` is given")]
+fn crear_ticket_con_media_sin_hash() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| {
+        ctx.crear_ticket(
+            alice(),
+            gate_id(1),
+            "My tickets".to_string(),
+            "descripcion".to_string(),
+            Some(10),
+            "5/100".parse().unwrap(),
+            Some("ipfs://media".to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+    });
+}
+
+#[test]
+#[should_panic(expected = "`referencia` must be an ipfs, ar or https URL")]
+fn crear_ticket_con_esquema_invalido() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| {
+        ctx.crear_ticket(
+            alice(),
+            gate_id(1),
+            "My tickets".to_string(),
+            "descripcion".to_string(),
+            Some(10),
+            "5/100".parse().unwrap(),
+            None,
+            None,
+            Some("http://ref".to_string()),
+            Some(REFERENCIA_HASH.to_string()),
+            None,
+            None,
+            None,
+        );
+    });
+}
+
+#[test]
+#[should_panic(expected = "`media_hash` is not a base64-encoded sha256")]
+fn crear_ticket_con_hash_invalido() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| {
+        ctx.crear_ticket(
+            alice(),
+            gate_id(1),
+            "My tickets".to_string(),
+            "descripcion".to_string(),
+            Some(10),
+            "5/100".parse().unwrap(),
+            Some("ipfs://media".to_string()),
+            Some("111".to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+    });
+}
+
+fn detalles_evento() -> DetallesEvento {
+    DetallesEvento {
+        lugar: "Luna Park".to_string(),
+        direccion: Some("Av. Eduardo Madero 470".to_string()),
+        coordenadas: Some(Coordenadas { latitud: -34.6022, longitud: -58.3685 }),
+        apertura_puertas_en: None,
+        edad_minima: Some(18),
+        categoria: CategoriaEvento::Concierto,
+    }
+}
+
+fn crear_ticket_con_detalles(
+    ctx: &mut MockedContext<NftContractChecker>,
+    n: u16,
+    detalles: DetallesEvento,
+) {
+    ctx.run_as(mintgate_admin(), |ctx| {
+        ctx.crear_ticket(
+            alice(),
+            gate_id(n),
+            "My tickets".to_string(),
+            "descripcion".to_string(),
+            Some(10),
+            "5/100".parse().unwrap(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(detalles),
+        );
+    });
+}
+
+#[test]
+fn crear_ticket_con_detalles_del_evento() {
+    let mut ctx = MockedContext::init();
+    crear_ticket_con_detalles(&mut ctx, 1, detalles_evento());
+
+    let ticket = ctx.get_ticket_por_id(gate_id(1)).unwrap();
+    assert_eq!(ticket.detalles, Some(detalles_evento()));
+    let extra: DetallesEvento = serde_json::from_str(&ticket.metadata.extra.unwrap()).unwrap();
+    assert_eq!(extra, detalles_evento());
+
+    assert_eq!(ctx.get_tickets_por_categoria(CategoriaEvento::Concierto, None, None).len(), 1);
+    assert_eq!(ctx.get_tickets_por_categoria(CategoriaEvento::Teatro, None, None).len(), 0);
+}
+
+#[test]
+fn paginar_tickets_por_categoria() {
+    let mut ctx = MockedContext::init();
+    let teatro = DetallesEvento { categoria: CategoriaEvento::Teatro, ..detalles_evento() };
+    crear_ticket_con_detalles(&mut ctx, 1, teatro);
+    crear_ticket_con_detalles(&mut ctx, 2, detalles_evento());
+    crear_ticket_con_detalles(&mut ctx, 3, detalles_evento());
+
+    let gates = |ctx: &MockedContext<NftContractChecker>, from_index, limit| {
+        ctx.get_tickets_por_categoria(CategoriaEvento::Concierto, Some(U64(from_index)), limit)
+            .into_iter()
+            .map(|ticket| ticket.gate_id)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(gates(&ctx, 0, Some(1)), vec![gate_id(2).to_string()]);
+    assert_eq!(gates(&ctx, 1, Some(1)), vec![gate_id(3).to_string()]);
+    assert_eq!(gates(&ctx, 2, None), Vec::<String>::new());
+}
+
+#[test]
+#[should_panic(expected = "`latitud` debe estar entre -90 y 90")]
+fn crear_ticket_con_coordenadas_invalidas() {
+    let mut ctx = MockedContext::init();
+    let detalles = DetallesEvento {
+        coordenadas: Some(Coordenadas { latitud: 134.6, longitud: -58.3685 }),
+        ..detalles_evento()
+    };
+    crear_ticket_con_detalles(&mut ctx, 1, detalles);
+}
+
+#[test]
+fn migrar_estado() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| {
+        ctx.crear_tickets(alice(), gate_id(1), 10, "5/100");
+        ctx.crear_tickets(alice(), gate_id(2), 10, "5/100");
+    });
+
+    ctx.run_as(bob(), |ctx| {
+        ctx.comprar_tickets(gate_id(1));
+        ctx.comprar_tickets(gate_id(2));
+        ctx.comprar_tickets(gate_id(1));
+    });
+
+    ctx.run_as(mintgate_admin(), |ctx| {
+        let contrato = std::mem::replace(&mut ctx.contrato, MockedContext::contrato());
+        env::state_write(&estado_base(contrato));
+        ctx.contrato = ContratoNft::iniciar_migracion();
+        assert_eq!(ctx.migrar_estado(U64(0), 1), Some(U64(1)));
+        assert_eq!(ctx.migrar_estado(U64(1), 3), Some(U64(4)));
+        assert_eq!(ctx.migrar_estado(U64(4), 4), None);
+        assert_eq!(ctx.siguiente_token_id, 3);
+
+        let token_ids = |gate_id| {
+            ctx.get_tokens_de_ticket(gate_id, None, None)
+                .into_iter()
+                .map(|token| token.token_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(token_ids(gate_id(1)), vec![U64(0), U64(2)]);
+        assert_eq!(token_ids(gate_id(2)), vec![U64(1)]);
+        let token = ctx.nft_token(U64(1)).unwrap();
+        assert_eq!(token.owner_id, bob().to_string());
+        assert_eq!(token.precio_venta, None);
+        assert_eq!(token.metadata_token.edicion, 1);
+        let token = ctx.nft_token(U64(2)).unwrap();
+        assert_eq!(token.metadata_token.edicion, 2);
+        assert_eq!(token.metadata.titulo, Some("My tickets #2 of 10".to_string()));
+        assert_eq!(ctx.nft_tokens_for_owner(bob(), None, None).len(), 3);
+
+        let ticket = ctx.get_ticket_por_id(gate_id(1)).unwrap();
+        assert_eq!(ticket.cantidad_actual, Some(8));
+        assert_eq!(ticket.cantidad_creada, 2);
+        assert_eq!(ticket.metadata.copias, Some(10));
+        assert_eq!(ticket.metadata.titulo, Some("My tickets".to_string()));
+        assert_eq!(ctx.get_tickets_de_creador(alice()).len(), 2);
+    });
+
+    ctx.run_as(charlie(), |ctx| {
+        assert_eq!(ctx.comprar_tickets(gate_id(1)), U64(3));
+    });
+}
+
+#[test]
+#[should_panic(expected = "MigrationStepMismatch")]
+fn migrar_estado_desde_otro_paso() {
+    let mut ctx = MockedContext::init();
+    ctx.run_as(mintgate_admin(), |ctx| {
+        ctx.crear_tickets(alice(), gate_id(1), 10, "5/100");
+    });
+
+    ctx.run_as(mintgate_admin(), |ctx| {
+        let contrato = std::mem::replace(&mut ctx.contrato, MockedContext::contrato());
+        env::state_write(&estado_base(contrato));
+        ctx.contrato = ContratoNft::iniciar_migracion();
+        ctx.migrar_estado(U64(1), 1);
+    });
+}
+
+/// Retorna las transferencias `(receiver_id, deposit)` creadas por la ultima llamada.
+fn transferencias() -> Vec<(String, u128)> {
+    get_created_receipts()
+        .into_iter()
+        .filter_map(|receipt| {
+            let receipt = serde_json::to_string(&receipt).unwrap();
+            let receipt: serde_json::Value = serde_json::from_str(&receipt).unwrap();
+            let deposit = receipt["actions"][0]["Transfer"]["deposit"].as_u64()?;
+            Some((receipt["receiver_id"].as_str().unwrap().to_string(), deposit as u128))
+        })
+        .collect()
+}
+
+/// Retorna las llamadas `(receiver_id, metodo)` a otros contratos creadas por la ultima llamada.
+fn llamadas() -> Vec<(String, String)> {
+    get_created_receipts()
+        .into_iter()
+        .filter_map(|receipt| {
+            let receipt = serde_json::to_string(&receipt).unwrap();
+            let receipt: serde_json::Value = serde_json::from_str(&receipt).unwrap();
+            let metodo = receipt["actions"][0]["FunctionCall"]["method_name"].as_str()?;
+            Some((receipt["receiver_id"].as_str().unwrap().to_string(), metodo.to_string()))
+        })
+        .collect()
+}
+
+impl MockedContext<ContratoMercado> {
+    /// Market con un fee de 3% que acepta los tokens del contrato `nft`.
+    fn mercado() -> Self {
+        let mut ctx = MockedContext::new(|| {
+            ContratoMercado::init_mercado(mintgate_admin(), "3/100".parse().unwrap(), vec![nft()])
+        });
+        ctx.context.current_account_id = market().to_string();
+        ctx
+    }
+
+    /// Pone en venta el token `token_id` de bob a `min_precio`, como lo hace `nft_approve`.
+    fn listar(&mut self, token_id: u64, min_precio: u128) {
+        self.listar_como(bob(), token_id, min_precio);
+    }
+
+    /// Pone en venta el token `token_id` de `owner_id` a `min_precio`.
+    fn listar_como(&mut self, owner_id: ValidAccountId, token_id: u64, min_precio: u128) {
+        self.aprobar(owner_id, token_id, approve_msg(min_precio));
+    }
+
+    /// Notifica al market la aprobación del token `token_id` de `owner_id` con `msg`.
+    fn aprobar(&mut self, owner_id: ValidAccountId, token_id: u64, msg: MarketApproveMsg) {
+        let msg = serde_json::to_string(&msg).unwrap();
+        self.run_as(nft(), |ctx| ctx.nft_on_approve(U64(token_id), owner_id, U64(1), msg));
+    }
+}
+
+/// `msg` de `nft_approve` para vender un token del gate 1 de alice a `min_precio`.
+fn approve_msg(min_precio: u128) -> MarketApproveMsg {
+    MarketApproveMsg {
+        min_precio: U128(min_precio),
+        gate_id: Some(gate_id(1)),
+        id_creador: Some(alice().to_string()),
+        oferta_id: None,
+        expira_en: None,
+        aprobados_id: None,
+    }
+}
+
+/// Token 0 de `owner_id` aprobado al market con `aprobados_id` 1, como lo retorna `nft_token`.
+fn token_aprobado(owner_id: ValidAccountId) -> Token {
+    let mut approvals = HashMap::new();
+    approvals.insert(market().to_string(), TokenApproval::new(1, U128(1000)));
+    Token {
+        token_id: U64(0),
+        gate_id: gate_id(1).to_string(),
+        owner_id: owner_id.to_string(),
+        created_at: 0,
+        modified_at: 0,
+        approvals,
+        approval_counter: U64(1),
+        precio_venta: None,
+        metadata_token: Default::default(),
+        metadata: Metadata::default(),
+    }
+}
+
+/// Compra el token 0 a 1030 y resuelve su `pago` como fallido.
+/// Retorna el token tal como estaba en venta.
+fn pago_fallido(ctx: &mut MockedContext<ContratoMercado>) -> TokenEnVenta {
+    ctx.listar(0, 1000);
+    let token = ctx.get_tokens_en_venta(None, None, None).pop().unwrap();
+    ctx.run_as(charlie(), |ctx| {
+        ctx.attach_deposit(1030).buy_token(nft(), U64(0));
+    });
+
+    testing_env_with_promise_results(ctx.context.clone(), PromiseResult::Failed);
+    ctx.contract.pago(token.clone(), charlie().to_string(), U128(1030), U128(1000));
+    assert_eq!(transferencias(), vec![(charlie().to_string(), 1030)]);
+    assert_eq!(
+        llamadas(),
+        vec![
+            (nft().to_string(), "nft_token".to_string()),
+            (market().to_string(), "relistar".to_string())
+        ]
+    );
+    assert!(ctx.get_tokens_en_venta(None, None, None).is_empty());
+    token
+}
+
+#[test]
+fn pago_fallido_vuelve_a_listar_con_la_aprobacion_vigente() {
+    let mut ctx = MockedContext::mercado();
+    let token = pago_fallido(&mut ctx);
+
+    let actual = serde_json::to_vec(&Some(token_aprobado(bob()))).unwrap();
+    testing_env_with_promise_results(ctx.context.clone(), PromiseResult::Successful(actual));
+    ctx.contract.relistar(token);
+    assert_eq!(ctx.get_tokens_en_venta(None, None, None).len(), 1);
+}
+
+#[test]
+fn pago_fallido_descarta_el_listado_si_cambio_el_dueno() {
+    let mut ctx = MockedContext::mercado();
+    let token = pago_fallido(&mut ctx);
+
+    let actual = serde_json::to_vec(&Some(token_aprobado(charlie()))).unwrap();
+    testing_env_with_promise_results(ctx.context.clone(), PromiseResult::Successful(actual));
+    ctx.contract.relistar(token);
+    assert!(ctx.get_tokens_en_venta(None, None, None).is_empty());
+}
+
+#[test]
+fn pago_fallido_descarta_el_listado_si_cambio_el_precio() {
+    let mut ctx = MockedContext::mercado();
+    let token = pago_fallido(&mut ctx);
+
+    let mut actual = token_aprobado(bob());
+    actual.approvals.insert(market().to_string(), TokenApproval::new(1, U128(800)));
+    let actual = serde_json::to_vec(&Some(actual)).unwrap();
+    testing_env_with_promise_results(ctx.context.clone(), PromiseResult::Successful(actual));
+    ctx.contract.relistar(token);
+    assert!(ctx.get_tokens_en_venta(None, None, None).is_empty());
+}
+
+#[test]
+fn pago_exitoso_descuenta_el_fee() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    let token = ctx.get_tokens_en_venta(None, None, None).pop().unwrap();
+    ctx.run_as(charlie(), |ctx| {
+        ctx.attach_deposit(1100).buy_token(nft(), U64(0));
+    });
+
+    let mut payout = HashMap::new();
+    payout.insert(alice().to_string(), U128(50));
+    payout.insert(bob().to_string(), U128(900));
+    let payout = serde_json::to_vec(&Some(payout)).unwrap();
+    testing_env_with_promise_results(ctx.context.clone(), PromiseResult::Successful(payout));
+    ctx.contract.pago(token, charlie().to_string(), U128(1100), U128(1067));
+
+    let mut pagos = transferencias();
+    pagos.sort();
+    assert_eq!(
+        pagos,
+        vec![(alice().to_string(), 50), (bob().to_string(), 117), (bob().to_string(), 900)]
+    );
+    assert_eq!(ctx.get_fees_acumulados(), U128(33));
 }
 
+#[test]
+fn pago_usa_el_fee_de_la_compra() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    let token = ctx.get_tokens_en_venta(None, None, None).pop().unwrap();
+    ctx.run_as(charlie(), |ctx| {
+        ctx.attach_deposit(1030).buy_token(nft(), U64(0));
+    });
+    ctx.run_as(mintgate_admin(), |ctx| ctx.set_fee_mercado("10/100".parse().unwrap()));
+
+    let mut payout = HashMap::new();
+    payout.insert(bob().to_string(), U128(1000));
+    let payout = serde_json::to_vec(&Some(payout)).unwrap();
+    testing_env_with_promise_results(ctx.context.clone(), PromiseResult::Successful(payout));
+    ctx.contract.pago(token, charlie().to_string(), U128(1030), U128(1000));
+    assert_eq!(transferencias(), vec![(bob().to_string(), 1000)]);
+    assert_eq!(ctx.get_fees_acumulados(), U128(30));
+}
+
+#[test]
+fn comprar_por_lote_cobra_el_precio_bruto_minimo() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.listar(1, 500);
+
+    ctx.run_as(charlie(), |ctx| {
+        ctx.attach_deposit(1030 + 515).buy_tokens(vec![(nft(), U64(0)), (nft(), U64(1))]);
+        assert_eq!(transferencias(), vec![]);
+        assert!(ctx.get_tokens_en_venta(None, None, None).is_empty());
+    });
+}
+
+#[test]
+#[should_panic(expected = "NotEnoughDepositToBuyToken")]
+fn comprar_por_lote_debajo_del_precio_bruto() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.run_as(charlie(), |ctx| {
+        ctx.attach_deposit(1029).buy_tokens(vec![(nft(), U64(0))]);
+    });
+}
+
+#[test]
+fn comprar_por_lote_reembolsa_el_excedente() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.run_as(charlie(), |ctx| {
+        ctx.attach_deposit(1100).buy_tokens(vec![(nft(), U64(0))]);
+        assert_eq!(transferencias(), vec![(charlie().to_string(), 70)]);
+    });
+}
+
+#[test]
+fn retirar_oferta_reembolsa_el_monto() {
+    let mut ctx = MockedContext::mercado();
+    ctx.run_as(charlie(), |ctx| {
+        let oferta_id = ctx.attach_deposit(800).ofertar_token(nft(), U64(0));
+        assert_eq!(ctx.get_ofertas_por_bidder(charlie()).len(), 1);
+
+        ctx.attach_deposit(0).retirar_oferta(oferta_id);
+        assert_eq!(transferencias(), vec![(charlie().to_string(), 800)]);
+        assert!(ctx.get_ofertas_por_bidder(charlie()).is_empty());
+    });
+}
+
+/// Aprueba el market sobre el token `token_id` de bob aceptando `oferta_id` a `min_precio`.
+fn aceptar_oferta(ctx: &mut MockedContext<ContratoMercado>, token_id: u64, oferta_id: U64) {
+    let msg = MarketApproveMsg { oferta_id: Some(oferta_id), ..approve_msg(1000) };
+    ctx.aprobar(bob(), token_id, msg);
+    assert_eq!(
+        llamadas(),
+        vec![
+            (nft().to_string(), "nft_transfer_payout".to_string()),
+            (market().to_string(), "resolver_oferta".to_string())
+        ]
+    );
+}
+
+#[test]
+fn aceptar_oferta_por_token() {
+    let mut ctx = MockedContext::mercado();
+    let mut oferta_id = U64(0);
+    ctx.run_as(charlie(), |ctx| oferta_id = ctx.attach_deposit(1030).ofertar_token(nft(), U64(0)));
+
+    aceptar_oferta(&mut ctx, 0, oferta_id);
+    assert!(ctx.get_ofertas_por_bidder(charlie()).is_empty());
+    assert!(ctx.get_tokens_en_venta(None, None, None).is_empty());
+}
+
+#[test]
+fn aceptar_oferta_por_gate() {
+    let mut ctx = MockedContext::mercado();
+    let mut oferta_id = U64(0);
+    ctx.run_as(charlie(), |ctx| {
+        oferta_id = ctx.attach_deposit(1030).ofertar_gate(nft(), gate_id(1))
+    });
+
+    aceptar_oferta(&mut ctx, 3, oferta_id);
+    assert!(ctx.get_ofertas_por_gate(gate_id(1)).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "OfertaNotForToken")]
+fn aceptar_oferta_de_otro_token() {
+    let mut ctx = MockedContext::mercado();
+    let mut oferta_id = U64(0);
+    ctx.run_as(charlie(), |ctx| oferta_id = ctx.attach_deposit(1030).ofertar_token(nft(), U64(0)));
+
+    aceptar_oferta(&mut ctx, 1, oferta_id);
+}
+
+#[test]
+fn aceptar_oferta_con_transferencia_fallida() {
+    let mut ctx = MockedContext::mercado();
+    let mut oferta_id = U64(0);
+    ctx.run_as(charlie(), |ctx| oferta_id = ctx.attach_deposit(1030).ofertar_token(nft(), U64(0)));
+    let oferta = ctx.get_ofertas_por_bidder(charlie()).pop().unwrap();
+
+    aceptar_oferta(&mut ctx, 0, oferta_id);
+    let token = TokenEnVenta {
+        contrato_id: nft().to_string(),
+        token_id: U64(0),
+        owner_id: bob().to_string(),
+        aprobados_id: U64(1),
+        min_precio: U128(1000),
+        gate_id: Some(gate_id(1).to_string()),
+        id_creador: Some(alice().to_string()),
+        expira_en: None,
+    };
+    testing_env_with_promise_results(ctx.context.clone(), PromiseResult::Failed);
+    ctx.contract.resolver_oferta(oferta, token, U128(1000));
+    assert_eq!(transferencias(), vec![]);
+    assert_eq!(ctx.get_ofertas_por_bidder(charlie()).len(), 1);
+}
+
+#[test]
+fn pujar_reembolsa_la_puja_superada() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.run_as(bob(), |ctx| ctx.subastar_token(nft(), U64(0), 60 * 60 * 1000, U128(100)));
+
+    ctx.run_as(charlie(), |ctx| {
+        ctx.attach_deposit(1100).pujar(nft(), U64(0));
+        assert_eq!(transferencias(), vec![]);
+    });
+    ctx.run_as(alice(), |ctx| {
+        ctx.attach_deposit(1200).pujar(nft(), U64(0));
+        assert_eq!(transferencias(), vec![(charlie().to_string(), 1100)]);
+        let puja = ctx.get_subasta(nft(), U64(0)).unwrap().mejor_puja.unwrap();
+        assert_eq!((puja.bidder_id, puja.monto), (alice().to_string(), U128(1200)));
+    });
+}
+
+/// Retorna `minutos` en nanosegundos, el formato de `block_timestamp`.
+fn minutos(minutos: u64) -> u64 {
+    minutos * 60 * 1000 * 1_000_000
+}
+
+#[test]
+fn pujar_cerca_del_final_extiende_la_subasta() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.run_as(bob(), |ctx| ctx.subastar_token(nft(), U64(0), 60 * 60 * 1000, U128(100)));
+
+    ctx.context.block_timestamp = minutos(30);
+    ctx.run_as(charlie(), |ctx| ctx.attach_deposit(1100).pujar(nft(), U64(0)));
+    assert_eq!(ctx.get_subasta(nft(), U64(0)).unwrap().fin_en, 60 * 60 * 1000);
+
+    ctx.context.block_timestamp = minutos(55);
+    ctx.run_as(alice(), |ctx| ctx.attach_deposit(1200).pujar(nft(), U64(0)));
+    assert_eq!(ctx.get_subasta(nft(), U64(0)).unwrap().fin_en, 65 * 60 * 1000);
+}
+
+#[test]
+#[should_panic(expected = "SubastaNotEnded")]
+fn liquidar_subasta_antes_del_final() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.run_as(bob(), |ctx| ctx.subastar_token(nft(), U64(0), 60 * 60 * 1000, U128(100)));
+    ctx.run_as(charlie(), |ctx| ctx.attach_deposit(1100).pujar(nft(), U64(0)));
+
+    ctx.context.block_timestamp = minutos(59);
+    ctx.run_as(alice(), |ctx| ctx.liquidar_subasta(nft(), U64(0)));
+}
+
+#[test]
+fn liquidar_subasta_transfiere_a_la_mejor_puja() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.run_as(bob(), |ctx| ctx.subastar_token(nft(), U64(0), 60 * 60 * 1000, U128(100)));
+    ctx.run_as(charlie(), |ctx| ctx.attach_deposit(1100).pujar(nft(), U64(0)));
+
+    ctx.context.block_timestamp = minutos(61);
+    ctx.run_as(alice(), |ctx| {
+        ctx.liquidar_subasta(nft(), U64(0));
+        assert_eq!(
+            llamadas(),
+            vec![
+                (nft().to_string(), "nft_transfer_payout".to_string()),
+                (market().to_string(), "pago".to_string())
+            ]
+        );
+    });
+    assert!(ctx.get_subasta(nft(), U64(0)).is_none());
+    assert!(ctx.get_tokens_en_venta(None, None, None).is_empty());
+}
+
+#[test]
+fn liquidar_subasta_sin_pujas_deja_el_token_en_venta() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.run_as(bob(), |ctx| ctx.subastar_token(nft(), U64(0), 60 * 60 * 1000, U128(100)));
+
+    ctx.context.block_timestamp = minutos(61);
+    ctx.run_as(alice(), |ctx| {
+        ctx.liquidar_subasta(nft(), U64(0));
+        assert_eq!(llamadas(), vec![]);
+    });
+    assert!(ctx.get_subasta(nft(), U64(0)).is_none());
+    assert_eq!(ctx.get_tokens_en_venta(None, None, None).len(), 1);
+}
+
+#[test]
+fn volver_a_listar_reemplaza_el_listado_anterior() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.listar_como(charlie(), 0, 500);
+
+    assert!(ctx.get_tokens_by_owner_id(bob(), None, None, None).is_empty());
+    assert_eq!(ctx.get_tokens_by_owner_id(charlie(), None, None, None).len(), 1);
+    assert_eq!(ctx.get_tokens_by_gate_id(gate_id(1), None, None, None).len(), 1);
+    assert_eq!(ctx.get_precio_piso(gate_id(1)), Some(U128(500)));
+    assert_eq!(ctx.get_histograma_precios(gate_id(1), U128(100)), vec![(U128(500), 1)]);
+    let baratos = ctx.get_mas_baratos(gate_id(1), 10);
+    assert_eq!(baratos.len(), 1);
+    assert_eq!(baratos[0].owner_id, charlie().to_string());
+}
+
+#[test]
+fn volver_a_listar_y_vender_el_mas_barato() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.listar(1, 1200);
+    ctx.listar_como(charlie(), 0, 500);
+
+    ctx.run_as(alice(), |ctx| {
+        ctx.attach_deposit(515).buy_cheapest(gate_id(1), U128(1000));
+        assert_eq!(llamadas()[0], (nft().to_string(), "nft_transfer_payout".to_string()));
+    });
+    assert_eq!(ctx.get_precio_piso(gate_id(1)), Some(U128(1200)));
+    assert!(ctx.get_tokens_by_owner_id(charlie(), None, None, None).is_empty());
+    assert_eq!(ctx.get_tokens_en_venta(None, None, None).len(), 1);
+}
+
+#[test]
+fn comprar_por_lote_reembolsa_todas_las_transferencias_fallidas() {
+    let mut ctx = MockedContext::mercado();
+    for token_id in 0..3 {
+        ctx.listar(token_id, 1000);
+    }
+    let en_venta = ctx.get_tokens_en_venta(None, None, None);
+    let tokens = (0..3).map(|token_id| (nft(), U64(token_id))).collect();
+    ctx.run_as(charlie(), |ctx| {
+        ctx.attach_deposit(3 * 1030).buy_tokens(tokens);
+        let resolver = get_created_receipts()
+            .into_iter()
+            .map(|receipt| serde_json::to_string(&receipt).unwrap())
+            .map(|receipt| serde_json::from_str::<serde_json::Value>(&receipt).unwrap())
+            .find(|receipt| {
+                receipt["actions"][0]["FunctionCall"]["method_name"] == "resolver_compra_por_lote"
+            })
+            .unwrap();
+        let gas = resolver["actions"][0]["FunctionCall"]["gas"].as_u64().unwrap();
+        assert!(gas >= 3 * (10_000_000_000_000 + 20_000_000_000_000));
+    });
+
+    let compras = en_venta.into_iter().map(|token| (token, U128(1030), U128(1000))).collect();
+    testing_env!(
+        ctx.context.clone(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        (0..3).map(|_| PromiseResult::Failed).collect()
+    );
+    ctx.contract.resolver_compra_por_lote(compras, charlie().to_string());
+    assert_eq!(transferencias(), vec![(charlie().to_string(), 1030); 3]);
+    let llamadas = llamadas();
+    assert_eq!(llamadas.iter().filter(|(_, metodo)| metodo == "nft_token").count(), 3);
+    assert_eq!(llamadas.iter().filter(|(_, metodo)| metodo == "relistar").count(), 3);
+}
+
+#[test]
+#[should_panic(expected = "NotEnoughGasToBatchBuy")]
+fn comprar_por_lote_sin_gas_para_liquidar() {
+    let mut ctx = MockedContext::mercado();
+    for token_id in 0..3 {
+        ctx.listar(token_id, 1000);
+    }
+    let tokens = (0..3).map(|token_id| (nft(), U64(token_id))).collect();
+    ctx.context.prepaid_gas = 100_000_000_000_000;
+    ctx.run_as(charlie(), |ctx| {
+        ctx.attach_deposit(3 * 1030).buy_tokens(tokens);
+    });
+}
+
+#[test]
+#[should_panic(expected = "ExpiryInPast")]
+fn listar_con_vencimiento_pasado() {
+    let mut ctx = MockedContext::mercado();
+    ctx.context.block_timestamp = minutos(10);
+    ctx.aprobar(bob(), 0, MarketApproveMsg { expira_en: Some(5 * 60 * 1000), ..approve_msg(1000) });
+}
+
+#[test]
+#[should_panic(expected = "ListingExpired")]
+fn comprar_listado_vencido() {
+    let mut ctx = MockedContext::mercado();
+    ctx.aprobar(
+        bob(),
+        0,
+        MarketApproveMsg { expira_en: Some(10 * 60 * 1000), ..approve_msg(1000) },
+    );
+
+    ctx.context.block_timestamp = minutos(10);
+    ctx.run_as(charlie(), |ctx| ctx.attach_deposit(1030).buy_token(nft(), U64(0)));
+}
+
+#[test]
+#[should_panic(expected = "ListingNotExpired")]
+fn quitar_listado_antes_de_vencer() {
+    let mut ctx = MockedContext::mercado();
+    ctx.aprobar(
+        bob(),
+        0,
+        MarketApproveMsg { expira_en: Some(10 * 60 * 1000), ..approve_msg(1000) },
+    );
+
+    ctx.context.block_timestamp = minutos(9);
+    ctx.run_as(alice(), |ctx| ctx.delist_expired(nft(), U64(0)));
+}
+
+#[test]
+fn quitar_listado_vencido() {
+    let mut ctx = MockedContext::mercado();
+    ctx.aprobar(
+        bob(),
+        0,
+        MarketApproveMsg { expira_en: Some(10 * 60 * 1000), ..approve_msg(1000) },
+    );
+    ctx.listar(1, 2000);
+
+    ctx.context.block_timestamp = minutos(10);
+    ctx.run_as(alice(), |ctx| {
+        assert_eq!(ctx.get_precio_piso(gate_id(1)), Some(U128(2000)));
+        ctx.delist_expired(nft(), U64(0));
+    });
+    assert_eq!(ctx.get_tokens_by_owner_id(bob(), None, None, None).len(), 1);
+    assert_eq!(ctx.get_tokens_by_gate_id(gate_id(1), None, None, None).len(), 1);
+}
+
+#[test]
+fn actualizar_precio_del_listado() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.listar(1, 2000);
+
+    ctx.run_as(nft(), |ctx| ctx.nft_on_price_update(U64(1), bob(), U64(1), U128(500)));
+    assert_eq!(ctx.get_precio_piso(gate_id(1)), Some(U128(500)));
+    let precios: Vec<_> =
+        ctx.get_mas_baratos(gate_id(1), 10).iter().map(|t| t.min_precio).collect();
+    assert_eq!(precios, vec![U128(500), U128(1000)]);
+}
+
+#[test]
+#[should_panic(expected = "NotTokenSeller")]
+fn actualizar_precio_de_otra_aprobacion() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.run_as(nft(), |ctx| ctx.nft_on_price_update(U64(0), bob(), U64(2), U128(500)));
+}
+
+/// Retorna los `token_id` de `tokens`.
+fn ids(tokens: Vec<TokenEnVenta>) -> Vec<u64> {
+    tokens.into_iter().map(|token| token.token_id.0).collect()
+}
+
+#[test]
+fn paginar_tokens_en_venta() {
+    let mut ctx = MockedContext::mercado();
+    for token_id in 0..5 {
+        ctx.listar(token_id, 500 - 100 * token_id as u128);
+    }
+
+    assert_eq!(ids(ctx.get_tokens_en_venta(Some(U64(1)), Some(2), None)), vec![1, 2]);
+    let filtro = FiltroVenta {
+        precio_min: Some(U128(200)),
+        precio_max: Some(U128(400)),
+        orden: Some(Orden::Asc),
+    };
+    assert_eq!(ids(ctx.get_tokens_en_venta(None, None, Some(filtro))), vec![3, 2, 1]);
+    let filtro = FiltroVenta { orden: Some(Orden::Desc), ..Default::default() };
+    assert_eq!(
+        ids(ctx.get_tokens_by_owner_id(bob(), Some(U64(3)), None, Some(filtro))),
+        vec![3, 4]
+    );
+}
+
+#[test]
+fn paginar_tokens_por_gate_ordenados() {
+    let mut ctx = MockedContext::mercado();
+    for token_id in 0..5 {
+        ctx.listar(token_id, 500 - 100 * token_id as u128);
+    }
+
+    let orden = |orden| FiltroVenta { orden: Some(orden), ..Default::default() };
+    let gate = |from_index, limit, filtro| {
+        ids(ctx.get_tokens_by_gate_id(gate_id(1), Some(U64(from_index)), Some(limit), Some(filtro)))
+    };
+    assert_eq!(gate(0, 10, orden(Orden::Asc)), vec![4, 3, 2, 1, 0]);
+    assert_eq!(gate(1, 2, orden(Orden::Asc)), vec![3, 2]);
+    assert_eq!(gate(1, 2, orden(Orden::Desc)), vec![1, 2]);
+    let filtro = FiltroVenta { precio_max: Some(U128(300)), ..orden(Orden::Desc) };
+    assert_eq!(gate(0, 10, filtro), vec![2, 3, 4]);
+    let filtro = FiltroVenta { precio_min: Some(U128(200)), ..orden(Orden::Desc) };
+    assert_eq!(gate(1, 10, filtro), vec![1, 2, 3]);
+}
+
+#[test]
+fn paginar_tokens_en_venta_hasta_el_maximo() {
+    let mut ctx = MockedContext::mercado();
+    for token_id in 0..101 {
+        ctx.listar(token_id, 1000);
+    }
+
+    assert_eq!(ctx.get_tokens_en_venta(None, None, None).len(), 100);
+    assert_eq!(ctx.get_tokens_en_venta(None, Some(1000), None).len(), 100);
+    let filtro = FiltroVenta { orden: Some(Orden::Asc), ..Default::default() };
+    assert_eq!(ctx.get_tokens_by_gate_id(gate_id(1), None, None, Some(filtro)).len(), 100);
+}
+
+#[test]
+#[should_panic(expected = "ContratoNftNotAllowed")]
+fn listar_desde_un_contrato_no_permitido() {
+    let mut ctx = MockedContext::mercado();
+    let msg = serde_json::to_string(&approve_msg(1000)).unwrap();
+    ctx.run_as(charlie(), |ctx| ctx.nft_on_approve(U64(0), bob(), U64(1), msg));
+}
+
+#[test]
+#[should_panic(expected = "ContratoNftNotAllowed")]
+fn listar_desde_un_contrato_quitado() {
+    let mut ctx = MockedContext::mercado();
+    ctx.listar(0, 1000);
+    ctx.run_as(mintgate_admin(), |ctx| ctx.quitar_contrato_nft(nft()));
+    assert!(ctx.get_contratos_nft().is_empty());
+    assert_eq!(ctx.get_tokens_en_venta(None, None, None).len(), 1);
+
+    ctx.listar(1, 1000);
+}
+
+#[test]
+#[should_panic(expected = "OwnerRestrictedOperation")]
+fn agregar_contrato_sin_ser_owner() {
+    let mut ctx = MockedContext::mercado();
+    ctx.run_as(bob(), |ctx| ctx.agregar_contrato_nft(charlie()));
+}
+
+#[test]
+fn cantidad_por_contrato() {
+    let mut ctx = MockedContext::mercado();
+    ctx.run_as(mintgate_admin(), |ctx| ctx.agregar_contrato_nft(charlie()));
+    ctx.listar(0, 1000);
+    ctx.listar(1, 1000);
+    let msg = serde_json::to_string(&approve_msg(1000)).unwrap();
+    ctx.run_as(charlie(), |ctx| ctx.nft_on_approve(U64(0), bob(), U64(1), msg));
+
+    let mut cantidades = ctx.get_cantidad_por_contrato();
+    cantidades.sort();
+    assert_eq!(cantidades, vec![(charlie().to_string(), 1), (nft().to_string(), 2)]);
+}
+
+/// Vende el token `token_id` de bob listado a `min_precio` a charlie por `deposit`,
+/// resolviendo su `pago` con todo el precio neto para bob.
+fn vender(
+    ctx: &mut MockedContext<ContratoMercado>,
+    token_id: u64,
+    min_precio: u128,
+    deposit: u128,
+) {
+    ctx.listar(token_id, min_precio);
+    let token = ctx.get_tokens_by_owner_id(bob(), None, None, None).pop().unwrap();
+    ctx.run_as(charlie(), |ctx| ctx.attach_deposit(deposit).buy_token(nft(), U64(token_id)));
+
+    let mut payout = HashMap::new();
+    payout.insert(bob().to_string(), U128(min_precio));
+    let payout = serde_json::to_vec(&Some(payout)).unwrap();
+    testing_env_with_promise_results(ctx.context.clone(), PromiseResult::Successful(payout));
+    ctx.contract.pago(token, charlie().to_string(), U128(deposit), U128(min_precio));
+}
+
+#[test]
+fn pago_exitoso_registra_la_venta() {
+    let mut ctx = MockedContext::mercado();
+    ctx.context.block_timestamp = minutos(1);
+    vender(&mut ctx, 0, 1000, 1030);
+    ctx.context.block_timestamp = minutos(2);
+    vender(&mut ctx, 1, 2000, 2061);
+
+    let historial = ctx.get_historial_token(nft(), U64(1));
+    assert_eq!(historial.len(), 1);
+    assert_eq!(historial[0].comprador_id, charlie().to_string());
+    assert_eq!(historial[0].vendedor_id, bob().to_string());
+    assert_eq!(historial[0].precio, U128(2061));
+    assert_eq!(historial[0].vendido_en, 2 * 60 * 1000);
+
+    let precios: Vec<_> = ctx.get_historial_gate(gate_id(1)).iter().map(|v| v.precio).collect();
+    assert_eq!(precios, vec![U128(1030), U128(2061)]);
+    let estadisticas = ctx.get_estadisticas_gate(gate_id(1));
+    assert_eq!(estadisticas.cantidad, 2);
+    assert_eq!(estadisticas.volumen, U128(3091));
+    assert_eq!(estadisticas.ultimo_precio, U128(2061));
+    assert_eq!(estadisticas.precio_promedio, U128(1545));
+}
+
+#[test]
+fn pago_fallido_no_registra_la_venta() {
+    let mut ctx = MockedContext::mercado();
+    pago_fallido(&mut ctx);
+
+    assert!(ctx.get_historial_token(nft(), U64(0)).is_empty());
+    assert!(ctx.get_historial_gate(gate_id(1)).is_empty());
+    assert_eq!(ctx.get_estadisticas_gate(gate_id(1)).cantidad, 0);
+}
+
+#[test]
+fn listar_por_lote_con_aprobaciones_reales() {
+    let mut ctx = MockedContext::mercado();
+    let tokens = vec![
+        (U64(0), MarketApproveMsg { aprobados_id: Some(U64(3)), ..approve_msg(1000) }),
+        (U64(1), MarketApproveMsg { aprobados_id: Some(U64(7)), ..approve_msg(500) }),
+    ];
+    ctx.run_as(nft(), |ctx| ctx.batch_on_approve(tokens, bob()));
+
+    let mut listados: Vec<_> = ctx
+        .get_tokens_by_owner_id(bob(), None, None, None)
+        .into_iter()
+        .map(|token| (token.token_id, token.aprobados_id, token.min_precio))
+        .collect();
+    listados.sort_by_key(|(token_id, _, _)| token_id.0);
+    assert_eq!(listados, vec![(U64(0), U64(3), U128(1000)), (U64(1), U64(7), U128(500))]);
+    assert_eq!(ctx.get_precio_piso(gate_id(1)), Some(U128(500)));
+}
+
+#[test]
+#[should_panic(expected = "MsgFormatApprovalIdMissing")]
+fn listar_por_lote_sin_aprobacion() {
+    let mut ctx = MockedContext::mercado();
+    let tokens = vec![(U64(0), approve_msg(1000))];
+    ctx.run_as(nft(), |ctx| ctx.batch_on_approve(tokens, bob()));
+}
+
+#[test]
+#[should_panic(expected = "ContratoNftNotAllowed")]
+fn listar_por_lote_desde_un_contrato_no_permitido() {
+    let mut ctx = MockedContext::mercado();
+    let tokens =
+        vec![(U64(0), MarketApproveMsg { aprobados_id: Some(U64(1)), ..approve_msg(1000) })];
+    ctx.run_as(charlie(), |ctx| ctx.batch_on_approve(tokens, bob()));
+}